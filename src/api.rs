@@ -0,0 +1,211 @@
+//! A JSON API for scripts and other tools that want to drive sessions without a websocket
+//!
+//! The API is only available if the server was given a token, which every request has to carry
+//! as `Authorization: Bearer <token>`. Sessions are addressed by their id, never their invite code.
+
+use actix::prelude::*;
+use actix_web::http::header;
+use actix_web::{error, web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::deck::{Card, Deck};
+use crate::outcome::OutcomeStrategyKind;
+use crate::poker_server::{
+    ChangeIssue, CloseSession, CreateSession, ExportSession, InspectSession, ListSessions, Server,
+    SessionId, VotingIssue,
+};
+
+#[derive(Clone)]
+pub struct ApiToken(pub String);
+
+// the state of a session as anyone outside of it gets to see it
+#[derive(Serialize, Debug)]
+pub struct SessionOverview {
+    pub session_id: SessionId,
+    pub invite_code: String,
+    pub outcome_strategy: OutcomeStrategyKind,
+    pub deck: Vec<Card>,
+    pub facilitator: String,
+    pub participants: Vec<String>,
+    pub observers: Vec<String>,
+    pub away_participants: Vec<String>,
+    // the votes are secret until they were revealed
+    pub current_issue: VotingIssue,
+    pub has_passphrase: bool,
+}
+
+#[derive(Deserialize)]
+struct NewSession {
+    #[serde(default)]
+    outcome_strategy: OutcomeStrategyKind,
+    #[serde(default)]
+    deck: Deck,
+    #[serde(default)]
+    passphrase: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NewIssue {
+    // an empty card means there's no card for the issue
+    #[serde(default)]
+    trello_card: String,
+}
+
+pub fn configure(config: &mut web::ServiceConfig, token: ApiToken) {
+    config.service(
+        web::scope("/api")
+            .data(token)
+            .route("/sessions", web::get().to(list_sessions))
+            .route("/sessions", web::post().to(create_session))
+            .route("/sessions/{session_id}", web::get().to(inspect_session))
+            .route("/sessions/{session_id}", web::delete().to(close_session))
+            .route("/sessions/{session_id}/issue", web::put().to(change_issue))
+            .route("/sessions/{session_id}/results", web::get().to(session_results)),
+    );
+}
+
+fn authorize(req: &HttpRequest) -> Result<(), Error> {
+    let token = req.app_data::<web::Data<ApiToken>>();
+    let given = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match (token, given) {
+        (Some(token), Some(given)) if token_matches(&token.0, given) => Ok(()),
+        _ => Err(error::ErrorUnauthorized("Missing or wrong API token")),
+    }
+}
+
+// the digests are compared without stopping at the first difference, so how long it takes
+// doesn't tell how much of the given token was right
+fn token_matches(token: &str, given: &str) -> bool {
+    let token = Sha256::digest(token.as_bytes());
+    let given = Sha256::digest(given.as_bytes());
+    token
+        .iter()
+        .zip(given.iter())
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+async fn ask<M>(srv: &Addr<Server>, msg: M) -> Result<M::Result, Error>
+where
+    M: Message + Send + 'static,
+    M::Result: Send,
+    Server: Handler<M>,
+{
+    srv.send(msg).await.map_err(error::ErrorInternalServerError)
+}
+
+fn unknown_session() -> Error {
+    error::ErrorNotFound("Unknown session")
+}
+
+async fn list_sessions(
+    req: HttpRequest,
+    srv: web::Data<Addr<Server>>,
+) -> Result<HttpResponse, Error> {
+    authorize(&req)?;
+    let sessions = ask(&srv, ListSessions).await?;
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+// nobody is in the new session yet; whoever joins it first becomes its facilitator
+async fn create_session(
+    req: HttpRequest,
+    body: web::Json<NewSession>,
+    srv: web::Data<Addr<Server>>,
+) -> Result<HttpResponse, Error> {
+    authorize(&req)?;
+    let NewSession {
+        outcome_strategy,
+        deck,
+        passphrase,
+    } = body.into_inner();
+    if !deck.is_valid() {
+        return Err(error::ErrorBadRequest("Invalid deck"));
+    }
+    let session = ask(
+        &srv,
+        CreateSession {
+            outcome_strategy,
+            deck,
+            passphrase,
+        },
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(session))
+}
+
+async fn inspect_session(
+    req: HttpRequest,
+    session_id: web::Path<SessionId>,
+    srv: web::Data<Addr<Server>>,
+) -> Result<HttpResponse, Error> {
+    authorize(&req)?;
+    let session_id = session_id.into_inner();
+    let session = ask(&srv, InspectSession { session_id })
+        .await?
+        .ok_or_else(unknown_session)?;
+    Ok(HttpResponse::Ok().json(session))
+}
+
+// everyone still in the session is disconnected from it
+async fn close_session(
+    req: HttpRequest,
+    session_id: web::Path<SessionId>,
+    srv: web::Data<Addr<Server>>,
+) -> Result<HttpResponse, Error> {
+    authorize(&req)?;
+    let session_id = session_id.into_inner();
+    if ask(&srv, CloseSession { session_id }).await? {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(unknown_session())
+    }
+}
+
+// the same as when the facilitator changes the topic, so the current issue is replaced
+async fn change_issue(
+    req: HttpRequest,
+    session_id: web::Path<SessionId>,
+    body: web::Json<NewIssue>,
+    srv: web::Data<Addr<Server>>,
+) -> Result<HttpResponse, Error> {
+    authorize(&req)?;
+    let change = ChangeIssue {
+        session_id: session_id.into_inner(),
+        trello_card: body.into_inner().trello_card,
+    };
+    let issue = ask(&srv, change).await?.ok_or_else(unknown_session)?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+// the issues whose votes were revealed, like the JSON export
+async fn session_results(
+    req: HttpRequest,
+    session_id: web::Path<SessionId>,
+    srv: web::Data<Addr<Server>>,
+) -> Result<HttpResponse, Error> {
+    authorize(&req)?;
+    let session_id = session_id.into_inner();
+    let export = ask(&srv, ExportSession { session_id })
+        .await?
+        .ok_or_else(unknown_session)?;
+    Ok(HttpResponse::Ok().json(export))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_exact_token_matches() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secret", "secreT"));
+        assert!(!token_matches("secret", "secret "));
+        assert!(!token_matches("secret", ""));
+    }
+}
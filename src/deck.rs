@@ -0,0 +1,141 @@
+//! The decks of cards a session can be played with
+
+use serde::{Deserialize, Serialize};
+
+use crate::poker_server::Vote;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Card {
+    pub label: String,
+    // cards without a value (like "∞") can't be averaged and rank above all other cards
+    pub value: Option<f64>,
+}
+
+impl Card {
+    fn new(label: &str, value: Option<f64>) -> Card {
+        Card {
+            label: label.to_string(),
+            value,
+        }
+    }
+}
+
+// the cards of every deck are listed in ascending order
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Deck {
+    Fibonacci,
+    ModifiedFibonacci,
+    TShirtSizes,
+    PowersOfTwo,
+    Custom(Vec<Card>),
+}
+
+impl Default for Deck {
+    fn default() -> Self {
+        Deck::Fibonacci
+    }
+}
+
+impl Deck {
+    pub fn cards(&self) -> Vec<Card> {
+        match self {
+            Deck::Fibonacci => numbered_cards(&[1.0, 2.0, 3.0, 5.0, 8.0, 13.0, 21.0], true),
+            Deck::ModifiedFibonacci => {
+                let mut cards = vec![Card::new("0", Some(0.0)), Card::new("½", Some(0.5))];
+                cards.extend(numbered_cards(
+                    &[1.0, 2.0, 3.0, 5.0, 8.0, 13.0, 20.0, 40.0, 100.0],
+                    true,
+                ));
+                cards
+            }
+            Deck::TShirtSizes => vec![
+                Card::new("XS", Some(1.0)),
+                Card::new("S", Some(2.0)),
+                Card::new("M", Some(3.0)),
+                Card::new("L", Some(5.0)),
+                Card::new("XL", Some(8.0)),
+                Card::new("XXL", Some(13.0)),
+            ],
+            Deck::PowersOfTwo => {
+                numbered_cards(&[0.0, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0], false)
+            }
+            Deck::Custom(cards) => cards.clone(),
+        }
+    }
+
+    // a deck needs at least one card and its labels must neither repeat
+    // nor clash with the votes that aren't cards
+    // outcomes are derived from the position of the cards, so the cards have to be in ascending
+    // order of their values, with the cards without a value last
+    pub fn is_valid(&self) -> bool {
+        let cards = self.cards();
+        !cards.is_empty()
+            && cards.iter().enumerate().all(|(index, card)| {
+                !card.label.is_empty()
+                    && matches!(Vote::from(card.label.clone()), Vote::Card(_))
+                    && cards[..index].iter().all(|other| other.label != card.label)
+            })
+            && cards.windows(2).all(|pair| match (pair[0].value, pair[1].value) {
+                (Some(lower), Some(upper)) => lower <= upper,
+                (Some(_), None) | (None, None) => true,
+                (None, Some(_)) => false,
+            })
+    }
+
+    // whether the vote is one that can be cast with this deck
+    pub fn accepts(&self, vote: &Vote) -> bool {
+        match vote {
+            Vote::Secret => false,
+            Vote::Unknown => true,
+            Vote::Card(label) => self.cards().iter().any(|card| &card.label == label),
+        }
+    }
+}
+
+fn numbered_cards(values: &[f64], with_infinity: bool) -> Vec<Card> {
+    let mut cards: Vec<Card> = values
+        .iter()
+        .map(|&value| Card::new(&value.to_string(), Some(value)))
+        .collect();
+    if with_infinity {
+        cards.push(Card::new("∞", None));
+    }
+    cards
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(cards: &[(&str, Option<f64>)]) -> Deck {
+        Deck::Custom(cards.iter().map(|&(label, value)| Card::new(label, value)).collect())
+    }
+
+    #[test]
+    fn predefined_decks_are_valid() {
+        for deck in [
+            Deck::Fibonacci,
+            Deck::ModifiedFibonacci,
+            Deck::TShirtSizes,
+            Deck::PowersOfTwo,
+        ] {
+            assert!(deck.is_valid(), "{:?}", deck);
+        }
+    }
+
+    #[test]
+    fn custom_decks_have_to_be_in_ascending_order() {
+        assert!(custom(&[("S", Some(2.0)), ("L", Some(5.0)), ("☕", None)]).is_valid());
+        assert!(custom(&[("a", Some(1.0)), ("b", Some(1.0))]).is_valid());
+        assert!(!custom(&[("L", Some(5.0)), ("S", Some(2.0))]).is_valid());
+        assert!(!custom(&[("S", Some(2.0)), ("☕", None), ("L", Some(5.0))]).is_valid());
+    }
+
+    #[test]
+    fn custom_decks_need_distinct_card_labels() {
+        assert!(!custom(&[]).is_valid());
+        assert!(!custom(&[("1", Some(1.0)), ("1", Some(2.0))]).is_valid());
+        assert!(!custom(&[("Unknown", None)]).is_valid());
+        assert!(!custom(&[("", Some(1.0))]).is_valid());
+    }
+}
@@ -0,0 +1,134 @@
+//! Exports the issues estimated in a session, so they can be transferred to an issue tracker
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::Serialize;
+
+use crate::poker_server::{SessionId, Vote};
+
+#[derive(Serialize, Debug)]
+pub struct ExportedIssue {
+    pub trello_card: Option<String>,
+    pub outcome: Option<Vote>,
+    // votes of the final round
+    pub votes: HashMap<String, Vote>,
+    pub rounds: u32,
+    pub opened_at: u64,
+    pub revealed_at: Option<u64>,
+    pub closed_at: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SessionExport {
+    pub session_id: SessionId,
+    pub issues: Vec<ExportedIssue>,
+}
+
+impl SessionExport {
+    // one row per issue, with a column for every participant who voted on any of them
+    pub fn to_csv(&self) -> String {
+        let participants: BTreeSet<&String> = self
+            .issues
+            .iter()
+            .flat_map(|issue| issue.votes.keys())
+            .collect();
+
+        let mut header = vec![
+            "card".to_string(),
+            "outcome".to_string(),
+            "rounds".to_string(),
+            "opened_at".to_string(),
+            "revealed_at".to_string(),
+            "closed_at".to_string(),
+        ];
+        header.extend(participants.iter().map(|name| name.to_string()));
+
+        let mut lines = vec![csv_line(&header)];
+        for issue in &self.issues {
+            let mut row = vec![
+                issue.trello_card.clone().unwrap_or_default(),
+                issue.outcome.clone().map(String::from).unwrap_or_default(),
+                issue.rounds.to_string(),
+                issue.opened_at.to_string(),
+                optional_to_string(issue.revealed_at),
+                optional_to_string(issue.closed_at),
+            ];
+            row.extend(participants.iter().map(|&name| {
+                issue.votes.get(name).cloned().map(String::from).unwrap_or_default()
+            }));
+            lines.push(csv_line(&row));
+        }
+        lines.join("\r\n") + "\r\n"
+    }
+}
+
+fn optional_to_string(value: Option<u64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+// fields are quoted whenever they contain a character that would otherwise break the format
+// spreadsheets take fields starting with one of =+-@ for formulas, so those are prefixed with a
+// quote to make them plain text; names and cards are entered by participants after all
+fn csv_line(fields: &[String]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            if field.starts_with(['=', '+', '-', '@']) {
+                format!("'{}", field)
+            } else {
+                field.clone()
+            }
+        })
+        .map(|field| {
+            if field.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(trello_card: &str, votes: &[(&str, &str)]) -> SessionExport {
+        SessionExport {
+            session_id: SessionId::default(),
+            issues: vec![ExportedIssue {
+                trello_card: Some(trello_card.to_string()),
+                outcome: Some(Vote::Card("5".to_string())),
+                votes: votes
+                    .iter()
+                    .map(|(name, card)| (name.to_string(), Vote::Card(card.to_string())))
+                    .collect(),
+                rounds: 1,
+                opened_at: 10,
+                revealed_at: Some(20),
+                closed_at: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn fields_that_would_break_the_format_are_quoted() {
+        let csv = export("https://trello.com/c/a, \"b\"\nc", &[("alice", "5")]).to_csv();
+        assert_eq!(
+            csv,
+            "card,outcome,rounds,opened_at,revealed_at,closed_at,alice\r\n\
+             \"https://trello.com/c/a, \"\"b\"\"\nc\",5,1,10,20,,5\r\n"
+        );
+    }
+
+    #[test]
+    fn fields_that_look_like_formulas_are_turned_into_text() {
+        let csv = export("=HYPERLINK(\"x\")", &[("@bob", "+1"), ("-carol", "1")]).to_csv();
+        assert_eq!(
+            csv,
+            "card,outcome,rounds,opened_at,revealed_at,closed_at,'-carol,'@bob\r\n\
+             \"'=HYPERLINK(\"\"x\"\")\",5,1,10,20,,1,'+1\r\n"
+        );
+    }
+}
@@ -0,0 +1,54 @@
+//! Invite codes are a short, human-friendly stand-in for a session id
+//!
+//! Session ids are long random strings that nobody wants to read out in a meeting, so every
+//! session additionally gets a code made of words, like "brave-quiet-otter-4172". Codes are
+//! case-insensitive and only valid for as long as their session lives. There are about 2.3
+//! billion of them (roughly 31 bits), so with the limit on unknown codes per address, guessing
+//! the code of a particular session takes a long time, but it isn't impossible. Sessions that must
+//! stay private need a passphrase.
+
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+
+const ADJECTIVES: [&str; 64] = [
+    "agile", "amber", "bold", "brave", "breezy", "bright", "calm", "clever", "cosmic", "crisp",
+    "curious", "daring", "dusty", "eager", "early", "fancy", "fearless", "fluffy", "frosty",
+    "gentle", "giant", "golden", "happy", "hidden", "humble", "icy", "jolly", "keen", "lively",
+    "lucky", "mellow", "merry", "mighty", "misty", "nimble", "noble", "odd", "patient", "plucky",
+    "polite", "proud", "quick", "quiet", "rapid", "rusty", "shiny", "silent", "silly", "sleepy",
+    "sly", "smooth", "snowy", "sparkly", "speedy", "steady", "sunny", "swift", "tidy", "tiny",
+    "vivid", "wild", "windy", "witty", "zesty",
+];
+
+const ANIMALS: [&str; 64] = [
+    "alpaca", "badger", "beaver", "bison", "camel", "cheetah", "cobra", "condor", "coyote",
+    "crane", "dingo", "dolphin", "eagle", "falcon", "ferret", "finch", "flamingo", "fox",
+    "gazelle", "gecko", "gibbon", "heron", "hippo", "ibis", "iguana", "jackal", "jaguar",
+    "koala", "lemur", "leopard", "llama", "lynx", "marmot", "meerkat", "mole", "moose", "newt",
+    "ocelot", "octopus", "otter", "owl", "panda", "panther", "parrot", "pelican", "penguin",
+    "puffin", "quokka", "rabbit", "raven", "salmon", "seal", "sloth", "squid", "stork", "tapir",
+    "tiger", "toucan", "turtle", "walrus", "weasel", "wombat", "yak", "zebra",
+];
+
+// a random code; it's up to the caller to make sure it isn't already in use
+pub fn generate_invite_code() -> String {
+    let mut rng = thread_rng();
+    let adjectives: Vec<&str> = ADJECTIVES.choose_multiple(&mut rng, 2).copied().collect();
+    format!(
+        "{}-{}-{}-{}",
+        adjectives[0],
+        adjectives[1],
+        ANIMALS.choose(&mut rng).unwrap(),
+        rng.gen_range(1000, 10000)
+    )
+}
+
+// people type codes in all sorts of ways, so they're compared in a canonical form
+pub fn normalize_invite_code(code: &str) -> String {
+    code.trim()
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
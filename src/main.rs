@@ -1,34 +1,73 @@
+use std::net::SocketAddr;
 use std::str::FromStr;
 use std::time::{Duration, Instant};
 
 use actix::prelude::*;
 use actix_files::{Files, NamedFile};
-use actix_web::{App, Error, HttpRequest, HttpResponse, HttpServer, web};
+use actix_web::{error, App, Error, HttpRequest, HttpResponse, HttpServer, web};
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web_actors::ws;
+use serde::Deserialize;
+use serde_json::Value;
 
+use crate::api::ApiToken;
+use crate::deck::Deck;
 use crate::poker_server::*;
+use crate::storage::{FileStorage, NoStorage, SessionStorage};
 
+mod api;
+mod deck;
+mod export;
+mod invite;
+mod outcome;
+mod passphrase;
 mod poker_server;
+mod session_log;
+mod storage;
 
 /// How often heartbeat pings are sent
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 /// How long before lack of client response causes a timeout
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Reason given when closing the connection of a kicked participant, if the facilitator gave none
+const KICK_REASON: &str = "Removed from the session by the facilitator";
+/// Reason given when closing the connections to a session that was closed
+const SESSION_CLOSED_REASON: &str = "The session was closed";
 
 struct ClientConnection {
     hb: std::time::Instant,
     server: Addr<Server>,
     participant_id: u32,
-    session_id: u32, // ensure that the client will only ever be in one session - keinen Quatsch machen!
+    session_id: SessionId, // ensure that the client will only ever be in one session - keinen Quatsch machen!
+    // the version agreed on in the handshake, nothing else is accepted before it
+    protocol_version: Option<u32>,
+    // what the client said it supports
+    capabilities: Vec<Capability>,
+    // the id of the request that is being processed, if the client gave it one
+    request_id: Option<RequestId>,
+    // where the client connects from, shared by all of its connections
+    peer_address: String,
+}
+
+// requests from clients may come with an id, which is echoed back in the answer to them
+#[derive(Deserialize)]
+struct ClientEnvelope {
+    #[serde(default)]
+    request_id: Option<RequestId>,
+    #[serde(flatten)]
+    message: PokerMessage,
 }
 
 impl ClientConnection {
-    pub fn new(server: Addr<Server>) -> ClientConnection {
+    pub fn new(server: Addr<Server>, peer_address: String) -> ClientConnection {
         ClientConnection {
             hb: Instant::now(),
             participant_id: 0,
-            session_id: 0,
+            session_id: SessionId::default(),
+            protocol_version: None,
+            capabilities: Vec::new(),
+            request_id: None,
+            peer_address,
             server,
         }
     }
@@ -43,6 +82,7 @@ impl Actor for ClientConnection {
         self.server
             .send(Connect {
                 addr: addr.recipient(),
+                peer_address: self.peer_address.clone(),
             })
             .into_actor(self)
             .then(|res, act, ctx| {
@@ -91,23 +131,161 @@ impl ClientConnection {
         });
     }
 
+    // tell the client right away about messages that the server can't make sense of
+    fn reject(
+        &self,
+        ctx: &mut ws::WebsocketContext<Self>,
+        code: ErrorCode,
+        request: Option<String>,
+    ) {
+        let response = PokerMessage::ErrorResponse {
+            code,
+            request,
+            request_id: self.request_id,
+        };
+        ctx.text(serde_json::to_string(&response).unwrap_or("Shit!".to_string()));
+    }
+
+    fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    // clients that don't know about roles can only ever vote
+    fn downgrade_role(&self, role: ParticipantRole) -> ParticipantRole {
+        if self.supports(Capability::ParticipantRoles) {
+            role
+        } else {
+            ParticipantRole::Voter
+        }
+    }
+
+    // agree on the protocol, or turn the client away if it's too old to talk to
+    fn shake_hands(
+        &mut self,
+        protocol_version: u32,
+        capabilities: Vec<Capability>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        if protocol_version < MIN_PROTOCOL_VERSION {
+            self.turn_away(protocol_version, Some("HandshakeRequest".to_string()), ctx);
+            return;
+        }
+        self.protocol_version = Some(protocol_version.min(PROTOCOL_VERSION));
+        self.capabilities = Capability::SUPPORTED
+            .iter()
+            .copied()
+            .filter(|capability| capabilities.contains(capability))
+            .collect();
+        let response = PokerMessage::HandshakeResponse {
+            protocol_version: protocol_version.min(PROTOCOL_VERSION),
+            capabilities: self.capabilities.clone(),
+        };
+        ctx.text(serde_json::to_string(&response).unwrap_or("Shit!".to_string()));
+        // the server never sees the handshake, so it's acknowledged right here like other requests
+        if let Some(request_id) = self.request_id {
+            let ack = PokerMessage::AckResponse { request_id };
+            ctx.text(serde_json::to_string(&ack).unwrap_or("Shit!".to_string()));
+        }
+    }
+
+    // tell the client that it's too old to talk to and hang up
+    fn turn_away(
+        &self,
+        protocol_version: u32,
+        request: Option<String>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        self.reject(ctx, ErrorCode::IncompatibleProtocolVersion, request);
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Protocol,
+            description: Some(format!(
+                "Protocol version {} is no longer supported",
+                protocol_version
+            )),
+        }));
+        ctx.stop();
+    }
+
     // invoked when a message from the browser has been received
-    fn process_message(&self, message: PokerMessage) {
+    // requests are downgraded to what the client said it supports
+    fn process_message(&mut self, message: PokerMessage, ctx: &mut ws::WebsocketContext<Self>) {
+        // clients that don't shake hands speak version 1, which is no longer supported
+        if self.protocol_version.is_none()
+            && !matches!(message, PokerMessage::HandshakeRequest { .. })
+        {
+            let request = serde_json::to_value(&message).ok().and_then(|v| message_type(&v));
+            self.turn_away(1, request, ctx);
+            return;
+        }
         let message = match message {
+            PokerMessage::HandshakeRequest {
+                protocol_version,
+                capabilities,
+            } => {
+                self.shake_hands(protocol_version, capabilities, ctx);
+                return;
+            }
             PokerMessage::CreateSessionRequest {
-                participant_name, ..
+                participant_name,
+                outcome_strategy,
+                deck,
+                role,
+                passphrase,
+                ..
             } => PokerMessage::CreateSessionRequest {
                 participant_id: self.participant_id,
                 participant_name,
+                outcome_strategy,
+                deck: if self.supports(Capability::CustomDecks) {
+                    deck
+                } else {
+                    Deck::default()
+                },
+                role: self.downgrade_role(role),
+                passphrase,
             },
             PokerMessage::JoinSessionRequest {
                 session_id,
+                invite_code,
                 participant_name,
+                role,
+                rejoin_token,
+                passphrase,
                 ..
             } => PokerMessage::JoinSessionRequest {
                 participant_id: self.participant_id,
                 session_id,
+                invite_code,
+                participant_name,
+                role: self.downgrade_role(role),
+                rejoin_token,
+                passphrase,
+            },
+            PokerMessage::FacilitatorTransferRequest {
+                participant_name, ..
+            } => PokerMessage::FacilitatorTransferRequest {
                 participant_name,
+                participant_id: self.participant_id,
+                session_id: self.session_id,
+            },
+            PokerMessage::ParticipantRenameRequest {
+                participant_name, ..
+            } => PokerMessage::ParticipantRenameRequest {
+                participant_name,
+                participant_id: self.participant_id,
+                session_id: self.session_id,
+            },
+            PokerMessage::ParticipantKickRequest {
+                participant_name,
+                ban,
+                reason,
+                ..
+            } => PokerMessage::ParticipantKickRequest {
+                participant_name,
+                ban,
+                reason,
+                participant_id: self.participant_id,
+                session_id: self.session_id,
             },
             PokerMessage::TopicChangeRequest { trello_card, .. } => {
                 PokerMessage::TopicChangeRequest {
@@ -116,38 +294,108 @@ impl ClientConnection {
                     session_id: self.session_id,
                 }
             }
+            PokerMessage::IssueHistoryRequest { .. } => PokerMessage::IssueHistoryRequest {
+                participant_id: self.participant_id,
+                session_id: self.session_id,
+            },
+            PokerMessage::ResyncRequest { seq, .. } => PokerMessage::ResyncRequest {
+                seq,
+                participant_id: self.participant_id,
+                session_id: self.session_id,
+            },
+            PokerMessage::VotingStartRequest {
+                issue_id,
+                countdown_seconds,
+                ..
+            } => PokerMessage::VotingStartRequest {
+                issue_id,
+                countdown_seconds: if self.supports(Capability::VotingCountdown) {
+                    countdown_seconds
+                } else {
+                    0
+                },
+                participant_id: self.participant_id,
+                session_id: self.session_id,
+            },
+            PokerMessage::NewRoundRequest { issue_id, .. } => PokerMessage::NewRoundRequest {
+                issue_id,
+                participant_id: self.participant_id,
+                session_id: self.session_id,
+            },
+            PokerMessage::IssueReopenRequest {
+                issue_id,
+                keep_votes,
+                ..
+            } => PokerMessage::IssueReopenRequest {
+                issue_id,
+                keep_votes,
+                participant_id: self.participant_id,
+                session_id: self.session_id,
+            },
             PokerMessage::VoteRequest { vote, issue_id, .. } => PokerMessage::VoteRequest {
                 vote,
                 issue_id,
                 participant_id: self.participant_id,
                 session_id: self.session_id,
             },
+            PokerMessage::VoteRetractionRequest { issue_id, .. } => {
+                PokerMessage::VoteRetractionRequest {
+                    issue_id,
+                    participant_id: self.participant_id,
+                    session_id: self.session_id,
+                }
+            }
             PokerMessage::VoteRevelationRequest { issue_id, .. } => {
                 PokerMessage::VoteRevelationRequest {
                     issue_id,
                     participant_id: self.participant_id,
+                    session_id: self.session_id,
                 }
             }
-            _ => message,
+            // everything else is only ever sent by the server
+            _ => {
+                let request = serde_json::to_value(&message).ok().and_then(|v| message_type(&v));
+                self.reject(ctx, ErrorCode::UnexpectedMessage, request);
+                return;
+            }
         };
-        self.server.do_send(message);
+        self.server.do_send(ClientRequest {
+            participant_id: self.participant_id,
+            request_id: self.request_id,
+            message,
+        });
     }
 }
 
 // invoked when the server sends back a message -> forward it through the socket
-impl Handler<PokerMessage> for ClientConnection {
+impl Handler<ServerMessage> for ClientConnection {
     type Result = ();
 
-    fn handle(&mut self, msg: PokerMessage, ctx: &mut Self::Context) {
-        match msg {
-            // if the server sends back a session id, jot it down so we can use it for Disconnect
-            PokerMessage::SessionInfoResponse { session_id, .. } => {
-                self.session_id = session_id;
-            }
-            _ => (),
+    fn handle(&mut self, msg: ServerMessage, ctx: &mut Self::Context) {
+        // if the server sends back a session id, jot it down so we can use it for Disconnect
+        if let PokerMessage::SessionInfoResponse { session_id, .. } = msg.message {
+            self.session_id = session_id;
         }
         let serialized = serde_json::to_string(&msg).unwrap_or("Shit!".to_string());
         ctx.text(serialized);
+        // the server already removed a kicked participant or a closed session, so there's no
+        // session left to leave
+        let close_reason = match msg.message {
+            PokerMessage::ParticipantKickAnnouncement { reason, .. } => Some(ws::CloseReason {
+                code: ws::CloseCode::Policy,
+                description: Some(reason.unwrap_or_else(|| KICK_REASON.to_string())),
+            }),
+            PokerMessage::SessionClosedAnnouncement { .. } => Some(ws::CloseReason {
+                code: ws::CloseCode::Normal,
+                description: Some(SESSION_CLOSED_REASON.to_string()),
+            }),
+            _ => None,
+        };
+        if let Some(close_reason) = close_reason {
+            self.session_id = SessionId::default();
+            ctx.close(Some(close_reason));
+            ctx.stop();
+        }
     }
 }
 
@@ -162,16 +410,26 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ClientConnection
                 self.hb = Instant::now();
             }
             Ok(ws::Message::Text(text)) => {
-                let deserialized = serde_json::from_str(&text);
+                let deserialized = serde_json::from_str::<ClientEnvelope>(&text);
                 match deserialized {
-                    Ok(message) => self.process_message(message),
+                    Ok(envelope) => {
+                        self.request_id = envelope.request_id;
+                        self.process_message(envelope.message, ctx)
+                    }
                     Err(e) => {
                         println!("failed to deserialize: {}, {}", text, e);
+                        // the JSON might still tell which request the client tried to send
+                        let value: Value = serde_json::from_str(&text).unwrap_or_default();
+                        self.request_id = value.get("request_id").and_then(Value::as_u64);
+                        let request = message_type(&value);
+                        self.reject(ctx, ErrorCode::MalformedMessage, request);
                     }
                 }
             }
             Ok(ws::Message::Binary(_bin)) => {
-                println!("Unexpected binary message received. What's going on?!")
+                println!("Unexpected binary message received. What's going on?!");
+                self.request_id = None;
+                self.reject(ctx, ErrorCode::MalformedMessage, None);
             }
             Ok(ws::Message::Close(reason)) => {
                 ctx.close(reason);
@@ -182,17 +440,73 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ClientConnection
     }
 }
 
+// messages are serialized as an object with the type of the message as its key,
+// next to the request id
+fn message_type(value: &Value) -> Option<String> {
+    value.as_object()?.keys().find(|key| *key != "request_id").cloned()
+}
+
 async fn websocket(
     req: HttpRequest,
     stream: web::Payload,
     srv: web::Data<Addr<Server>>,
 ) -> Result<HttpResponse, Error> {
-    let connection = ClientConnection::new(srv.get_ref().clone());
+    let connection = ClientConnection::new(srv.get_ref().clone(), peer_address(&req));
     ws::start(connection, &req, stream)
 }
 
+// the address of the client, as told by a proxy in front of the server if there is one,
+// without the port so that reconnecting doesn't change it
+fn peer_address(req: &HttpRequest) -> String {
+    let address = req
+        .connection_info()
+        .realip_remote_addr()
+        .unwrap_or("unknown")
+        .to_string();
+    match address.parse::<SocketAddr>() {
+        Ok(socket_address) => socket_address.ip().to_string(),
+        Err(_) => address,
+    }
+}
+
+// exports the revealed issues of a session as either CSV or JSON
+async fn export_session(
+    path: web::Path<(SessionId, String)>,
+    srv: web::Data<Addr<Server>>,
+) -> Result<HttpResponse, Error> {
+    let (session_id, format) = path.into_inner();
+    let export = srv
+        .send(ExportSession { session_id })
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorNotFound("Unknown session"))?;
+    match format.as_str() {
+        "csv" => Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .body(export.to_csv())),
+        "json" => Ok(HttpResponse::Ok().json(export)),
+        _ => Err(error::ErrorBadRequest("Unsupported export format")),
+    }
+}
+
+// the log of a session, to replay what happened in it
+async fn export_session_log(
+    session_id: web::Path<SessionId>,
+    srv: web::Data<Addr<Server>>,
+) -> Result<HttpResponse, Error> {
+    let log = srv
+        .send(ExportSessionLog {
+            session_id: session_id.into_inner(),
+        })
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorNotFound("Unknown session"))?;
+    Ok(HttpResponse::Ok().json(log))
+}
+
 const DEFAULT_PORT: u16 = 8080;
 const DEFAULT_INTERFACE: &str = "127.0.0.1";
+const DEFAULT_RECONNECT_GRACE_PERIOD: Duration = Duration::from_secs(30);
 
 fn listen_port() -> u16 {
     match std::env::var("PORT") {
@@ -222,14 +536,63 @@ fn listen_interface() -> String {
     }
 }
 
+fn reconnect_grace_period() -> Duration {
+    match std::env::var("RECONNECT_GRACE_PERIOD") {
+        Ok(seconds) => match u64::from_str(seconds.as_str()) {
+            Ok(seconds) => Duration::from_secs(seconds),
+            Err(_) => {
+                println!("Failed to parse reconnect grace period {}", seconds);
+                DEFAULT_RECONNECT_GRACE_PERIOD
+            }
+        },
+        Err(_) => DEFAULT_RECONNECT_GRACE_PERIOD,
+    }
+}
+
+// the JSON API is only available if there is a token to protect it with
+fn api_token() -> Option<ApiToken> {
+    match std::env::var("API_TOKEN") {
+        Ok(token) if !token.is_empty() => Some(ApiToken(token)),
+        _ => {
+            println!("No $API_TOKEN set, the JSON API is disabled");
+            None
+        }
+    }
+}
+
+// sessions are only kept in memory unless a directory to store them in is configured
+fn session_storage() -> std::io::Result<Box<dyn SessionStorage>> {
+    match std::env::var("SESSION_STORAGE_DIR") {
+        Ok(directory) => {
+            println!("Storing sessions in {}", directory);
+            Ok(Box::new(FileStorage::open(directory.into())?))
+        }
+        Err(_) => {
+            println!("No $SESSION_STORAGE_DIR set, sessions will only be kept in memory");
+            Ok(Box::new(NoStorage))
+        }
+    }
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let poker_server = Server::new().start();
+    let poker_server = Server::new(session_storage()?, reconnect_grace_period()).start();
+    let api_token = api_token();
     let listen_on = format!("{}:{}", listen_interface(), listen_port());
     let http_server = HttpServer::new(move || {
         App::new()
             .data(poker_server.clone())
             .route("/ws", web::get().to(websocket))
+            .route(
+                "/sessions/{session_id}/export.{format}",
+                web::get().to(export_session),
+            )
+            .route("/sessions/{session_id}/log", web::get().to(export_session_log))
+            .configure(|config| {
+                if let Some(token) = api_token.clone() {
+                    api::configure(config, token);
+                }
+            })
             .service(
                 Files::new("/", "./public")
                     .prefer_utf8(true)
@@ -0,0 +1,292 @@
+//! Derives the outcome of a voting round from the votes that were cast
+//!
+//! Votes of `Unknown` ("?") count as abstentions: they are ignored entirely and only
+//! produce an `Unknown` outcome if nobody cast an actual card.
+//! Cards without a numeric value (like "∞") can't be averaged and rank above every
+//! other card of the deck.
+//! Each session picks one of the `OutcomeStrategy` implementations when it is created.
+//! Strategies work on the ranks of the cards that were played, i.e. their position in the deck.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::deck::Card;
+use crate::poker_server::Vote;
+
+pub trait OutcomeStrategy {
+    // derive the rank of the outcome from the ranks of the cards that were played,
+    // `None` if there isn't one
+    fn outcome(&self, deck: &[Card], ranks: &mut [usize]) -> Option<usize>;
+
+    fn determine_outcome(&self, deck: &[Card], votes: &HashMap<String, Vote>) -> Vote {
+        let mut ranks = cast_ranks(deck, votes);
+        if ranks.is_empty() {
+            return Vote::Unknown;
+        }
+        self.outcome(deck, &mut ranks)
+            .map_or(Vote::Unknown, |rank| Vote::Card(deck[rank].label.clone()))
+    }
+}
+
+// the strategies a session can be configured with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutcomeStrategyKind {
+    Standard,
+    Consensus,
+    Majority,
+    Median,
+    MeanRoundedUp,
+    PessimisticMax,
+}
+
+impl Default for OutcomeStrategyKind {
+    fn default() -> Self {
+        OutcomeStrategyKind::Standard
+    }
+}
+
+impl OutcomeStrategyKind {
+    pub fn strategy(&self) -> &'static dyn OutcomeStrategy {
+        match self {
+            OutcomeStrategyKind::Standard => &StandardStrategy,
+            OutcomeStrategyKind::Consensus => &ConsensusStrategy,
+            OutcomeStrategyKind::Majority => &MajorityStrategy,
+            OutcomeStrategyKind::Median => &MedianStrategy,
+            OutcomeStrategyKind::MeanRoundedUp => &MeanRoundedUpStrategy,
+            OutcomeStrategyKind::PessimisticMax => &PessimisticMaxStrategy,
+        }
+    }
+}
+
+// a consensus card if there is one, otherwise the card with an absolute majority,
+// otherwise the median
+pub struct StandardStrategy;
+
+impl OutcomeStrategy for StandardStrategy {
+    fn outcome(&self, deck: &[Card], ranks: &mut [usize]) -> Option<usize> {
+        consensus(ranks)
+            .or_else(|| majority(ranks))
+            .or_else(|| median(deck, ranks))
+    }
+}
+
+// only an outcome if everyone played the same card
+pub struct ConsensusStrategy;
+
+impl OutcomeStrategy for ConsensusStrategy {
+    fn outcome(&self, _deck: &[Card], ranks: &mut [usize]) -> Option<usize> {
+        consensus(ranks)
+    }
+}
+
+// only an outcome if more than half of the participants played the same card
+pub struct MajorityStrategy;
+
+impl OutcomeStrategy for MajorityStrategy {
+    fn outcome(&self, _deck: &[Card], ranks: &mut [usize]) -> Option<usize> {
+        majority(ranks)
+    }
+}
+
+pub struct MedianStrategy;
+
+impl OutcomeStrategy for MedianStrategy {
+    fn outcome(&self, deck: &[Card], ranks: &mut [usize]) -> Option<usize> {
+        median(deck, ranks)
+    }
+}
+
+// the average of all cards, rounded up to the nearest card in the deck
+pub struct MeanRoundedUpStrategy;
+
+impl OutcomeStrategy for MeanRoundedUpStrategy {
+    fn outcome(&self, deck: &[Card], ranks: &mut [usize]) -> Option<usize> {
+        let values: Vec<f64> = ranks.iter().filter_map(|&rank| deck[rank].value).collect();
+        if values.len() < ranks.len() {
+            // someone played a card like "∞", which can't be averaged but trumps everything else
+            return maximum(ranks);
+        }
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        round_up_to_card(deck, mean)
+    }
+}
+
+// the highest card anyone played
+pub struct PessimisticMaxStrategy;
+
+impl OutcomeStrategy for PessimisticMaxStrategy {
+    fn outcome(&self, _deck: &[Card], ranks: &mut [usize]) -> Option<usize> {
+        maximum(ranks)
+    }
+}
+
+// only keep the votes which are actual cards from the deck
+fn cast_ranks(deck: &[Card], votes: &HashMap<String, Vote>) -> Vec<usize> {
+    votes
+        .values()
+        .filter_map(|vote| match vote {
+            Vote::Card(label) => deck.iter().position(|card| &card.label == label),
+            _ => None,
+        })
+        .collect()
+}
+
+fn consensus(ranks: &[usize]) -> Option<usize> {
+    let first = ranks.first()?;
+    if ranks.iter().all(|rank| rank == first) {
+        Some(*first)
+    } else {
+        None
+    }
+}
+
+// the card that more than half of the participants voted for
+fn majority(ranks: &[usize]) -> Option<usize> {
+    ranks
+        .iter()
+        .find(|candidate| ranks.iter().filter(|rank| rank == candidate).count() * 2 > ranks.len())
+        .copied()
+}
+
+// the median of all cards; if it falls between two cards, their average is
+// rounded up to the nearest card in the deck
+fn median(deck: &[Card], ranks: &mut [usize]) -> Option<usize> {
+    ranks.sort_unstable();
+    let middle = ranks.len() / 2;
+    if ranks.len() % 2 == 1 {
+        return Some(ranks[middle]);
+    }
+    let (lower, upper) = (ranks[middle - 1], ranks[middle]);
+    match (deck[lower].value, deck[upper].value) {
+        (Some(lower_value), Some(upper_value)) => {
+            round_up_to_card(deck, (lower_value + upper_value) / 2.0)
+        }
+        // at least one of them can't be averaged
+        _ => Some(upper),
+    }
+}
+
+fn maximum(ranks: &[usize]) -> Option<usize> {
+    ranks.iter().max().copied()
+}
+
+fn round_up_to_card(deck: &[Card], value: f64) -> Option<usize> {
+    deck.iter()
+        .position(|card| card.value.map_or(false, |card_value| card_value >= value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::Deck;
+
+    // the outcome of the votes with the Fibonacci deck (1, 2, 3, 5, 8, 13, 21, ∞)
+    fn outcome(kind: OutcomeStrategyKind, votes: &[&str]) -> Vote {
+        let votes: HashMap<String, Vote> = votes
+            .iter()
+            .enumerate()
+            .map(|(index, &vote)| (format!("participant {}", index), Vote::from(vote.to_string())))
+            .collect();
+        kind.strategy()
+            .determine_outcome(&Deck::Fibonacci.cards(), &votes)
+    }
+
+    fn card(label: &str) -> Vote {
+        Vote::Card(label.to_string())
+    }
+
+    #[test]
+    fn consensus_wins() {
+        assert_eq!(outcome(OutcomeStrategyKind::Standard, &["5", "5", "5"]), card("5"));
+    }
+
+    #[test]
+    fn absolute_majority_wins() {
+        assert_eq!(outcome(OutcomeStrategyKind::Standard, &["3", "3", "13"]), card("3"));
+    }
+
+    #[test]
+    fn median_of_odd_count_is_the_middle_card() {
+        assert_eq!(outcome(OutcomeStrategyKind::Standard, &["1", "5", "21"]), card("5"));
+    }
+
+    #[test]
+    fn median_of_even_count_is_rounded_up_to_a_card() {
+        // (2 + 5) / 2 = 3.5
+        assert_eq!(outcome(OutcomeStrategyKind::Standard, &["1", "2", "5", "13"]), card("5"));
+        // (3 + 5) / 2 = 4
+        assert_eq!(outcome(OutcomeStrategyKind::Standard, &["3", "3", "5", "5"]), card("5"));
+    }
+
+    #[test]
+    fn infinity_in_the_middle_of_the_median_wins() {
+        assert_eq!(outcome(OutcomeStrategyKind::Standard, &["3", "∞"]), card("∞"));
+        assert_eq!(outcome(OutcomeStrategyKind::Standard, &["3", "8", "∞"]), card("8"));
+    }
+
+    #[test]
+    fn unknown_votes_are_abstentions() {
+        assert_eq!(outcome(OutcomeStrategyKind::Standard, &["8", "Unknown"]), card("8"));
+    }
+
+    #[test]
+    fn only_unknown_votes_have_no_outcome() {
+        assert_eq!(outcome(OutcomeStrategyKind::Standard, &["Unknown", "Unknown"]), Vote::Unknown);
+        assert_eq!(outcome(OutcomeStrategyKind::Standard, &[]), Vote::Unknown);
+    }
+
+    #[test]
+    fn consensus_strategy_needs_everyone_to_agree() {
+        assert_eq!(outcome(OutcomeStrategyKind::Consensus, &["5", "5", "Unknown"]), card("5"));
+        assert_eq!(outcome(OutcomeStrategyKind::Consensus, &["5", "5", "8"]), Vote::Unknown);
+    }
+
+    #[test]
+    fn majority_strategy_needs_more_than_half() {
+        assert_eq!(outcome(OutcomeStrategyKind::Majority, &["5", "5", "8"]), card("5"));
+        // a tie is no majority
+        assert_eq!(outcome(OutcomeStrategyKind::Majority, &["3", "3", "5", "5"]), Vote::Unknown);
+    }
+
+    #[test]
+    fn median_strategy_rounds_up_between_cards() {
+        assert_eq!(outcome(OutcomeStrategyKind::Median, &["5", "5", "8", "8"]), card("8"));
+        assert_eq!(outcome(OutcomeStrategyKind::Median, &["1", "2"]), card("2"));
+        assert_eq!(outcome(OutcomeStrategyKind::Median, &["5", "∞"]), card("∞"));
+    }
+
+    #[test]
+    fn mean_strategy_rounds_up_to_a_card() {
+        // (1 + 2 + 3) / 3 = 2
+        assert_eq!(outcome(OutcomeStrategyKind::MeanRoundedUp, &["1", "2", "3"]), card("2"));
+        // (3 + 5 + 8) / 3 = 5.33
+        assert_eq!(outcome(OutcomeStrategyKind::MeanRoundedUp, &["3", "5", "8"]), card("8"));
+    }
+
+    #[test]
+    fn infinity_can_not_be_averaged() {
+        assert_eq!(outcome(OutcomeStrategyKind::MeanRoundedUp, &["1", "∞"]), card("∞"));
+    }
+
+    #[test]
+    fn pessimistic_max_strategy_takes_the_highest_card() {
+        let votes = ["1", "13", "Unknown"];
+        assert_eq!(outcome(OutcomeStrategyKind::PessimisticMax, &votes), card("13"));
+        assert_eq!(outcome(OutcomeStrategyKind::PessimisticMax, &["8", "∞"]), card("∞"));
+    }
+
+    #[test]
+    fn no_strategy_has_an_outcome_without_cards() {
+        for kind in [
+            OutcomeStrategyKind::Standard,
+            OutcomeStrategyKind::Consensus,
+            OutcomeStrategyKind::Majority,
+            OutcomeStrategyKind::Median,
+            OutcomeStrategyKind::MeanRoundedUp,
+            OutcomeStrategyKind::PessimisticMax,
+        ] {
+            assert_eq!(outcome(kind, &["Unknown"]), Vote::Unknown);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Passphrases are only ever kept as a salted hash
+//!
+//! Sessions end up on disk when they're stored across restarts, so the passphrase a session was
+//! created with is hashed right away. Joining participants are let in if their passphrase hashes
+//! to the same value.
+
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PassphraseHash {
+    salt: String,
+    hash: String,
+}
+
+impl PassphraseHash {
+    pub fn new(passphrase: &str) -> PassphraseHash {
+        let salt: String = thread_rng().sample_iter(&Alphanumeric).take(16).collect();
+        let hash = hash(&salt, passphrase);
+        PassphraseHash { salt, hash }
+    }
+
+    pub fn matches(&self, passphrase: &str) -> bool {
+        hash(&self.salt, passphrase) == self.hash
+    }
+}
+
+// hex encoded SHA-256 of the salt followed by the passphrase
+fn hash(salt: &str, passphrase: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(passphrase.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_passphrase_matches_its_hash() {
+        let hash = PassphraseHash::new("open sesame");
+        assert!(hash.matches("open sesame"));
+        assert!(!hash.matches("open sesame "));
+        assert!(!hash.matches(""));
+    }
+
+    #[test]
+    fn the_same_passphrase_is_salted_differently() {
+        let first = PassphraseHash::new("open sesame");
+        let second = PassphraseHash::new("open sesame");
+        assert_ne!(first, second);
+        assert!(!serde_json::to_string(&first).unwrap().contains("sesame"));
+    }
+}
@@ -1,96 +1,523 @@
 //! The `PokerServer` is an actor that maintains a list of sessions,
 //! their participants and current votes
 
-use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use actix::prelude::*;
+use rand::distributions::Alphanumeric;
 use rand::{self, thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 
+use crate::api::SessionOverview;
+use crate::deck::{Card, Deck};
+use crate::export::{ExportedIssue, SessionExport};
+use crate::invite::{generate_invite_code, normalize_invite_code};
+use crate::outcome::OutcomeStrategyKind;
+use crate::passphrase::PassphraseHash;
+use crate::session_log::{redacted_log, LogEntry, SessionChange};
+use crate::storage::SessionStorage;
+
 // helper function to generate a random id string
 fn generate_random_id() -> u32 {
     thread_rng().gen::<u32>()
 }
 
+// secret that only the participant it was handed out to knows
+fn generate_token() -> String {
+    thread_rng().sample_iter(&Alphanumeric).take(32).collect()
+}
+
+// identifies a session; unlike participant and issue ids it's long enough that it can't be
+// guessed, since knowing it is all it takes to join the session
+// the zero id stands for "no session", like the zero participant id does
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(try_from = "String", into = "String")]
+pub struct SessionId(u128);
+
+impl SessionId {
+    pub(crate) fn generate() -> SessionId {
+        SessionId(thread_rng().gen::<u128>())
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl fmt::Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl TryFrom<String> for SessionId {
+    type Error = String;
+
+    fn try_from(id: String) -> Result<Self, Self::Error> {
+        if id.len() != 32 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("invalid session id {}", id));
+        }
+        u128::from_str_radix(&id, 16)
+            .map(SessionId)
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl From<SessionId> for String {
+    fn from(id: SessionId) -> Self {
+        id.to_string()
+    }
+}
+
+// seconds since the unix epoch, to record when something happened
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
 #[derive(Message)]
 #[rtype(result = "u32")] // return participant id
 pub struct Connect {
-    pub addr: Recipient<PokerMessage>,
+    pub addr: Recipient<ServerMessage>,
+    pub peer_address: String,
+}
+
+// chosen by the client to tell the server's answers to its requests apart
+pub type RequestId = u64;
+
+// a request as received from a client, which the server acknowledges if it carries an id
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ClientRequest {
+    pub participant_id: u32,
+    pub request_id: Option<RequestId>,
+    pub message: PokerMessage,
+}
+
+// numbers the events of a session in the order they happened, starting at 1
+pub type SequenceNumber = u64;
+
+// a message for a client, numbered if it announces an event of the client's session
+#[derive(Message, Serialize)]
+#[rtype(result = "()")]
+pub struct ServerMessage {
+    #[serde(flatten)]
+    pub message: PokerMessage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<SequenceNumber>,
 }
 
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Disconnect {
     pub participant_id: u32,
-    pub session_id: u32,
+    pub session_id: SessionId,
+}
+
+// the revealed issues of a session, `None` if there is no such session
+#[derive(Message)]
+#[rtype(result = "Option<SessionExport>")]
+pub struct ExportSession {
+    pub session_id: SessionId,
+}
+
+// all sessions the server currently keeps
+#[derive(Message)]
+#[rtype(result = "Vec<SessionOverview>")]
+pub struct ListSessions;
+
+// `None` if there is no such session
+#[derive(Message)]
+#[rtype(result = "Option<SessionOverview>")]
+pub struct InspectSession {
+    pub session_id: SessionId,
+}
+
+// a session nobody has joined yet; the deck has to be valid
+#[derive(Message)]
+#[rtype(result = "SessionOverview")]
+pub struct CreateSession {
+    pub outcome_strategy: OutcomeStrategyKind,
+    pub deck: Deck,
+    pub passphrase: Option<String>,
+}
+
+// the issue that is current afterwards, `None` if there is no such session
+#[derive(Message)]
+#[rtype(result = "Option<VotingIssue>")]
+pub struct ChangeIssue {
+    pub session_id: SessionId,
+    pub trello_card: String,
+}
+
+// whether there was such a session
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct CloseSession {
+    pub session_id: SessionId,
+}
+
+// what happened in a session so far, without its secrets; `None` if there is no such session
+#[derive(Message)]
+#[rtype(result = "Option<Vec<LogEntry>>")]
+pub struct ExportSessionLog {
+    pub session_id: SessionId,
 }
 
 fn zero_id() -> u32 {
     0
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SessionJoinError {
     UnknownSession,
     ParticipantNameTaken,
+    InvalidDeck,
+    WrongPassphrase,
+    // too many wrong passphrases or invite codes were tried recently, so none are taken for now
+    TooManyAttempts,
+    // the facilitator banned the name from the session
+    ParticipantNameBanned,
+}
+
+// why a request was rejected, so clients can tell their bugs apart from the server's
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    // the message isn't valid JSON or doesn't match any known request
+    MalformedMessage,
+    // the message is one that only the server sends
+    UnexpectedMessage,
+    // the sender doesn't participate in the session the request is about
+    NotInSession,
+    // the request refers to an issue other than the current one
+    IssueMismatch,
+    // the current issue isn't in the state the request requires
+    InvalidVotingState,
+    // observers don't vote
+    NotAVoter,
+    // the vote isn't one of the session's cards
+    VoteNotAccepted,
+    // there is no vote to take back
+    NoVoteCast,
+    // nobody in the session goes by the given name
+    UnknownParticipant,
+    // the votes can't be kept when everyone voted, they would be revealed again right away
+    NoVoteMissing,
+    // the client speaks a version of the protocol that is too old
+    IncompatibleProtocolVersion,
+    // only the facilitator may send the request
+    PermissionDenied,
+    // joining or creating a session failed; these mirror `SessionJoinError` one-to-one
+    UnknownSession,
+    ParticipantNameTaken,
+    InvalidDeck,
+    WrongPassphrase,
+    TooManyAttempts,
+    ParticipantNameBanned,
+}
+
+impl From<SessionJoinError> for ErrorCode {
+    fn from(error: SessionJoinError) -> Self {
+        match error {
+            SessionJoinError::UnknownSession => ErrorCode::UnknownSession,
+            SessionJoinError::ParticipantNameTaken => ErrorCode::ParticipantNameTaken,
+            SessionJoinError::InvalidDeck => ErrorCode::InvalidDeck,
+            SessionJoinError::WrongPassphrase => ErrorCode::WrongPassphrase,
+            SessionJoinError::TooManyAttempts => ErrorCode::TooManyAttempts,
+            SessionJoinError::ParticipantNameBanned => ErrorCode::ParticipantNameBanned,
+        }
+    }
+}
+
+// the version of the websocket protocol spoken by this server
+pub const PROTOCOL_VERSION: u32 = 2;
+// clients speaking an older version are turned away; version 1 still had numeric session ids
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+
+// optional features of the protocol; the server doesn't expect clients to support all of them
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    // decks other than the default one
+    CustomDecks,
+    // joining as an observer rather than a voter
+    ParticipantRoles,
+    // a countdown before voting starts
+    VotingCountdown,
+    // whatever newer clients support that this server doesn't know about
+    #[serde(other)]
+    Unknown,
+}
+
+impl Capability {
+    pub const SUPPORTED: [Capability; 3] = [
+        Capability::CustomDecks,
+        Capability::ParticipantRoles,
+        Capability::VotingCountdown,
+    ];
 }
 
-#[derive(Serialize, Deserialize, Debug, Message)]
+#[derive(Serialize, Deserialize, Debug, Clone, Message)]
 #[rtype(result = "()")] // responses are sent out asynchronously
                         // participant ids always use Option<> so that they can be deserialized from JSON
                         // the participant id is then filled in through the `ClientConnection`
 pub enum PokerMessage {
+    // the client tells the server which protocol it speaks, before sending anything else
+    HandshakeRequest {
+        protocol_version: u32,
+        #[serde(default)]
+        capabilities: Vec<Capability>,
+    },
+    // the server answers with the version both sides speak and the capabilities both support
+    HandshakeResponse {
+        protocol_version: u32,
+        capabilities: Vec<Capability>,
+    },
     // a client requests to create a session
     CreateSessionRequest {
         #[serde(default = "zero_id")]
         participant_id: u32,
         participant_name: String,
+        #[serde(default)]
+        outcome_strategy: OutcomeStrategyKind,
+        #[serde(default)]
+        deck: Deck,
+        #[serde(default)]
+        role: ParticipantRole,
+        // if given, everyone who wants to join the session has to know it
+        #[serde(default)]
+        passphrase: Option<String>,
     },
     // a client requests to join a session
     JoinSessionRequest {
         #[serde(default = "zero_id")]
         participant_id: u32,
-        session_id: u32,
+        // either the id of the session or its invite code has to be given
+        #[serde(default)]
+        session_id: SessionId,
+        #[serde(default)]
+        invite_code: Option<String>,
         participant_name: String,
+        #[serde(default)]
+        role: ParticipantRole,
+        // the token from a previous `SessionInfoResponse`, to take over the participant's seat
+        #[serde(default)]
+        rejoin_token: Option<String>,
+        #[serde(default)]
+        passphrase: Option<String>,
     },
     // the server sends the client the state of the current session
     SessionInfoResponse {
-        session_id: u32,
+        session_id: SessionId,
+        // short code which others can use to join the session
+        invite_code: String,
         current_issue: VotingIssue,
         current_participants: Vec<String>,
+        // the participants who don't vote; they are also listed in `current_participants`
+        observers: Vec<String>,
+        outcome_strategy: OutcomeStrategyKind,
+        // the cards that can be played in this session
+        deck: Vec<Card>,
+        // name of the participant who is allowed to control the session
+        facilitator: String,
+        // participants whose connection dropped but who may still come back
+        away_participants: Vec<String>,
+        // secret which allows the recipient to reclaim their seat from another connection
+        rejoin_token: String,
+        // sequence number of the latest event this state reflects
+        seq: SequenceNumber,
     },
-    // the server notifies the client that joining the session failed
+    // deprecated: the server notifies the client that joining the session failed, right after
+    // the `ErrorResponse` with the same code
     SessionJoinErrorResponse {
-        session_id: u32,
+        session_id: SessionId,
         error: SessionJoinError,
     },
     // the server announces to everyone else that a new participant entered their session
     ParticipantJoinAnnouncement {
         participant_name: String,
+        role: ParticipantRole,
     },
     // the server announces to everyone else that someone left their session
     ParticipantLeaveAnnouncement {
         participant_name: String,
     },
+    // the server announces that someone lost their connection but keeps their seat for a while
+    ParticipantAwayAnnouncement {
+        participant_name: String,
+    },
+    // the server announces that someone who was away has reconnected
+    ParticipantReturnAnnouncement {
+        participant_name: String,
+    },
+    // the facilitator hands their role over to another participant
+    FacilitatorTransferRequest {
+        #[serde(default = "zero_id")]
+        participant_id: u32,
+        #[serde(default)]
+        session_id: SessionId,
+        participant_name: String,
+    },
+    // the server announces who is facilitating the session from now on
+    FacilitatorAnnouncement {
+        participant_name: String,
+    },
+    // a participant wants to be known by another name
+    ParticipantRenameRequest {
+        #[serde(default = "zero_id")]
+        participant_id: u32,
+        #[serde(default)]
+        session_id: SessionId,
+        participant_name: String,
+    },
+    // deprecated: the server notifies the client that they can't take the name they asked for,
+    // right after the `ErrorResponse` with the same code
+    ParticipantRenameErrorResponse {
+        session_id: SessionId,
+        participant_name: String,
+        error: SessionJoinError,
+    },
+    // the server announces to everyone in the session that someone changed their name
+    ParticipantRenameAnnouncement {
+        old_participant_name: String,
+        participant_name: String,
+    },
+    // the facilitator removes someone from the session, optionally for good
+    ParticipantKickRequest {
+        #[serde(default = "zero_id")]
+        participant_id: u32,
+        #[serde(default)]
+        session_id: SessionId,
+        participant_name: String,
+        #[serde(default)]
+        ban: bool,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    // the server tells a participant that they were removed from the session,
+    // their connection is closed right after
+    ParticipantKickAnnouncement {
+        session_id: SessionId,
+        reason: Option<String>,
+    },
+    // the server tells everyone in a session that it was closed, their connection is closed
+    // right after
+    SessionClosedAnnouncement {
+        session_id: SessionId,
+    },
+    // the server notifies the client that their request was rejected
+    ErrorResponse {
+        code: ErrorCode,
+        // the type of the rejected request, if it could be determined
+        request: Option<String>,
+        // the id the client gave the rejected request, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        request_id: Option<RequestId>,
+    },
+    // the server confirms that it handled a request which the client gave an id
+    AckResponse {
+        request_id: RequestId,
+    },
+    // deprecated: the server notifies the client that only the facilitator may send the request,
+    // right after the `ErrorResponse` with the `PermissionDenied` code
+    PermissionDeniedResponse {
+        session_id: SessionId,
+        request: String,
+    },
     // the client requests to change the issue being voted upon
     TopicChangeRequest {
         #[serde(default = "zero_id")]
         participant_id: u32,
-        #[serde(default = "zero_id")]
-        session_id: u32,
+        #[serde(default)]
+        session_id: SessionId,
         trello_card: String,
     },
     // the server announces a new issue being voted on
     VotingIssueAnnouncement {
         voting_issue: VotingIssue,
     },
+    // the client requests all issues of the session which were voted on before the current one
+    IssueHistoryRequest {
+        #[serde(default = "zero_id")]
+        participant_id: u32,
+        #[serde(default)]
+        session_id: SessionId,
+    },
+    // the server sends the client the previous issues, oldest first
+    IssueHistoryResponse {
+        session_id: SessionId,
+        issues: Vec<VotingIssue>,
+    },
+    // the client requests the events it missed after the one with the given sequence number
+    // the server replays them, or sends a SessionInfoResponse if they aren't kept anymore
+    ResyncRequest {
+        #[serde(default = "zero_id")]
+        participant_id: u32,
+        #[serde(default)]
+        session_id: SessionId,
+        seq: SequenceNumber,
+    },
+    // the client requests to open the current issue for voting, optionally after a countdown
+    VotingStartRequest {
+        #[serde(default = "zero_id")]
+        participant_id: u32,
+        #[serde(default)]
+        session_id: SessionId,
+        issue_id: u32,
+        #[serde(default)]
+        countdown_seconds: u64,
+    },
+    // the server announces that voting on the current issue will start after the countdown
+    VotingCountdownAnnouncement {
+        issue_id: u32,
+        countdown_seconds: u64,
+    },
+    // the server announces that the current issue has moved on to another state
+    VotingStateAnnouncement {
+        issue_id: u32,
+        state: VotingState,
+    },
+    // the client requests to vote on the current issue again after its votes were revealed
+    NewRoundRequest {
+        #[serde(default = "zero_id")]
+        participant_id: u32,
+        #[serde(default)]
+        session_id: SessionId,
+        issue_id: u32,
+    },
+    // the server announces that the votes were cleared for another round of voting
+    NewRoundAnnouncement {
+        issue_id: u32,
+        round: u32,
+    },
+    // the client requests to take back the revelation of the current issue's votes,
+    // e.g. because they were revealed by accident
+    IssueReopenRequest {
+        #[serde(default = "zero_id")]
+        participant_id: u32,
+        #[serde(default)]
+        session_id: SessionId,
+        issue_id: u32,
+        // otherwise everyone has to vote again
+        #[serde(default)]
+        keep_votes: bool,
+    },
+    // the server announces that the issue is open for voting again,
+    // with the votes that were kept hidden from everyone but their voter
+    IssueReopenAnnouncement {
+        voting_issue: VotingIssue,
+    },
     // the client sends the server its vote
     VoteRequest {
         #[serde(default = "zero_id")]
         participant_id: u32,
-        #[serde(default = "zero_id")]
-        session_id: u32,
+        #[serde(default)]
+        session_id: SessionId,
         issue_id: u32,
         vote: Vote,
     },
@@ -98,11 +525,28 @@ pub enum PokerMessage {
     VoteReceiptAnnouncement {
         participant_name: String,
         issue_id: u32,
+        // whether the vote replaced one the participant had already cast
+        changed: bool,
+    },
+    // the client takes back their vote before the votes were revealed
+    VoteRetractionRequest {
+        #[serde(default = "zero_id")]
+        participant_id: u32,
+        #[serde(default)]
+        session_id: SessionId,
+        issue_id: u32,
+    },
+    // the server announces that someone took back their vote and hasn't voted anymore
+    VoteRetractionAnnouncement {
+        participant_name: String,
+        issue_id: u32,
     },
-    // the client requests for the votes to be revealed
+    // the client requests for the votes to be revealed, even if not everyone voted yet
     VoteRevelationRequest {
         #[serde(default = "zero_id")]
         participant_id: u32,
+        #[serde(default)]
+        session_id: SessionId,
         issue_id: u32,
     },
     // the server reveals all the votes
@@ -110,23 +554,83 @@ pub enum PokerMessage {
         issue_id: u32,
         votes: HashMap<String, Vote>,
         outcome: Vote,
+        // participants who hadn't voted when the votes were revealed
+        missing_voters: Vec<String>,
     },
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl PokerMessage {
+    // correlate a rejection with the request it rejects, returns whether it was one
+    fn attach_request_id(&mut self, id: RequestId) -> bool {
+        match self {
+            PokerMessage::ErrorResponse { request_id, .. } => {
+                *request_id = Some(id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // the message as the given participant gets to see it
+    fn blinded_for(&self, participant_name: &String) -> PokerMessage {
+        match self {
+            PokerMessage::IssueReopenAnnouncement { voting_issue } => {
+                PokerMessage::IssueReopenAnnouncement {
+                    voting_issue: voting_issue.clone_blinded(Some(participant_name)),
+                }
+            }
+            _ => self.clone(),
+        }
+    }
+
+    // the session affected by a request from a client
+    pub fn session_id(&self) -> Option<SessionId> {
+        match self {
+            PokerMessage::FacilitatorTransferRequest { session_id, .. }
+            | PokerMessage::ParticipantRenameRequest { session_id, .. }
+            | PokerMessage::ParticipantKickRequest { session_id, .. }
+            | PokerMessage::TopicChangeRequest { session_id, .. }
+            | PokerMessage::VotingStartRequest { session_id, .. }
+            | PokerMessage::NewRoundRequest { session_id, .. }
+            | PokerMessage::IssueReopenRequest { session_id, .. }
+            | PokerMessage::VoteRequest { session_id, .. }
+            | PokerMessage::VoteRetractionRequest { session_id, .. }
+            | PokerMessage::VoteRevelationRequest { session_id, .. } => Some(*session_id),
+            _ => None,
+        }
+    }
+}
+
+// votes are serialized as plain strings: either one of the special votes or the label of a card
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "String", into = "String")]
 pub enum Vote {
     Secret,
     Unknown,
-    One,
-    Two,
-    Three,
-    Five,
-    Eight,
-    Thirteen,
-    TwentyOne,
-    Infinite,
+    Card(String),
+}
+
+impl From<String> for Vote {
+    fn from(label: String) -> Self {
+        match label.as_str() {
+            "Secret" => Vote::Secret,
+            "Unknown" => Vote::Unknown,
+            _ => Vote::Card(label),
+        }
+    }
+}
+
+impl From<Vote> for String {
+    fn from(vote: Vote) -> Self {
+        match vote {
+            Vote::Secret => "Secret".to_string(),
+            Vote::Unknown => "Unknown".to_string(),
+            Vote::Card(label) => label,
+        }
+    }
 }
 
+// an issue is announced in `Opening`, accepts votes in `Voting` and has been revealed in `Closing`
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum VotingState {
     Opening,
@@ -134,26 +638,79 @@ pub enum VotingState {
     Closing,
 }
 
+// observers are part of the session but don't vote, so nobody has to wait for them
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParticipantRole {
+    Voter,
+    Observer,
+}
+
+impl Default for ParticipantRole {
+    fn default() -> Self {
+        ParticipantRole::Voter
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct VotingParticipant {
     id: u32,
     name: String,
+    #[serde(default)]
+    role: ParticipantRole,
+    // unix timestamp of when the participant's connection dropped
+    #[serde(default)]
+    away_since: Option<u64>,
+    #[serde(default = "generate_token")]
+    rejoin_token: String,
 }
 
 impl Clone for VotingParticipant {
     fn clone(&self) -> Self {
         VotingParticipant {
-            id: self.id.clone(),
+            id: self.id,
             name: self.name.clone(),
+            role: self.role,
+            away_since: self.away_since,
+            rejoin_token: self.rejoin_token.clone(),
         }
     }
 }
 
 impl VotingParticipant {
-    pub fn new(id: u32, name: String) -> VotingParticipant {
-        VotingParticipant { id, name }
+    pub fn new(
+        id: u32,
+        name: String,
+        role: ParticipantRole,
+        rejoin_token: String,
+    ) -> VotingParticipant {
+        VotingParticipant {
+            id,
+            name,
+            role,
+            away_since: None,
+            rejoin_token,
+        }
+    }
+
+    pub fn is_away(&self) -> bool {
+        self.away_since.is_some()
+    }
+
+    pub fn is_voter(&self) -> bool {
+        self.role == ParticipantRole::Voter
     }
 }
 
+// the revealed votes of a previous round on the same issue
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VotingRound {
+    round: u32,
+    outcome: Option<Vote>,
+    votes: HashMap<String, Vote>,
+    #[serde(default)]
+    revealed_at: Option<u64>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct VotingIssue {
     id: u32,
@@ -163,16 +720,31 @@ pub struct VotingIssue {
     outcome: Option<Vote>,
     // participant id to votes
     votes: HashMap<String, Vote>,
+    // rounds start counting at 1
+    round: u32,
+    previous_rounds: Vec<VotingRound>,
+    // unix timestamps of the issue's lifecycle
+    #[serde(default)]
+    opened_at: u64,
+    #[serde(default)]
+    revealed_at: Option<u64>,
+    #[serde(default)]
+    closed_at: Option<u64>,
 }
 
 impl Clone for VotingIssue {
     fn clone(&self) -> Self {
         VotingIssue {
-            id: self.id.clone(),
+            id: self.id,
             state: self.state.clone(),
             outcome: self.outcome.clone(),
             votes: self.votes.clone(),
             trello_card: self.trello_card.clone(),
+            round: self.round,
+            previous_rounds: self.previous_rounds.clone(),
+            opened_at: self.opened_at,
+            revealed_at: self.revealed_at,
+            closed_at: self.closed_at,
         }
     }
 }
@@ -185,6 +757,68 @@ impl VotingIssue {
             outcome: None,
             state: VotingState::Opening,
             trello_card,
+            round: 1,
+            previous_rounds: Vec::new(),
+            opened_at: unix_timestamp(),
+            revealed_at: None,
+            closed_at: None,
+        }
+    }
+
+    // archive the revealed votes and start voting all over again
+    pub fn start_new_round(&mut self) {
+        self.previous_rounds.push(VotingRound {
+            round: self.round,
+            outcome: self.outcome.take(),
+            votes: std::mem::take(&mut self.votes),
+            revealed_at: self.revealed_at.take(),
+        });
+        self.round += 1;
+        self.state = VotingState::Voting;
+    }
+
+    // go back to voting as if the votes had never been revealed
+    pub fn reopen(&mut self, keep_votes: bool) {
+        if !keep_votes {
+            self.votes.clear();
+        }
+        self.outcome = None;
+        self.revealed_at = None;
+        self.state = VotingState::Voting;
+    }
+
+    // votes are keyed by name, so they have to follow the participant
+    // someone else's vote under the new name is never overwritten, the old one stays put instead
+    pub fn rename_voter(&mut self, old_name: &str, new_name: &str) {
+        let rounds = self.previous_rounds.iter_mut().map(|round| &mut round.votes);
+        for votes in std::iter::once(&mut self.votes).chain(rounds) {
+            if votes.contains_key(new_name) {
+                continue;
+            }
+            if let Some(vote) = votes.remove(old_name) {
+                votes.insert(new_name.to_string(), vote);
+            }
+        }
+    }
+
+    // whether the name voted on the issue, in any of its rounds
+    pub fn has_voter(&self, name: &str) -> bool {
+        self.votes.contains_key(name)
+            || self
+                .previous_rounds
+                .iter()
+                .any(|round| round.votes.contains_key(name))
+    }
+
+    pub fn export(&self) -> ExportedIssue {
+        ExportedIssue {
+            trello_card: self.trello_card.clone(),
+            outcome: self.outcome.clone(),
+            votes: self.votes.clone(),
+            rounds: self.round,
+            opened_at: self.opened_at,
+            revealed_at: self.revealed_at,
+            closed_at: self.closed_at,
         }
     }
 
@@ -205,77 +839,639 @@ impl VotingIssue {
                 .collect(),
         };
         VotingIssue {
-            id: self.id.clone(),
+            id: self.id,
             votes,
             outcome: self.outcome.clone(),
             state: self.state.clone(),
             trello_card: self.trello_card.clone(),
+            round: self.round,
+            previous_rounds: self.previous_rounds.clone(),
+            opened_at: self.opened_at,
+            revealed_at: self.revealed_at,
+            closed_at: self.closed_at,
         }
     }
 }
 
-struct VotingSession {
-    id: u32,
+// how many events of a session are kept for clients that have to catch up
+const MAX_RECENT_EVENTS: usize = 100;
+
+// a message which was announced to the participants of a session
+#[derive(Clone, Debug)]
+pub struct SessionEvent {
+    seq: SequenceNumber,
+    message: PokerMessage,
+}
+
+#[derive(Serialize, Debug)]
+pub struct VotingSession {
+    id: SessionId,
+    // short code which can be shared instead of the id
+    invite_code: String,
     participants: Vec<VotingParticipant>,
     current_issue: VotingIssue,
+    outcome_strategy: OutcomeStrategyKind,
+    deck: Deck,
+    // issues that were revealed before the current one, oldest first
+    history: Vec<VotingIssue>,
+    // name of the participant who is allowed to control the session
+    facilitator: String,
+    // secret that has to be given to join the session, if any
+    passphrase_hash: Option<PassphraseHash>,
+    // names the facilitator doesn't want to see in the session again
+    banned_names: Vec<String>,
+    // sequence number of the latest event
+    last_seq: SequenceNumber,
+    // the latest events, oldest first
+    #[serde(skip)]
+    recent_events: VecDeque<SessionEvent>,
+    // every change since the session was created, oldest first
+    log: Vec<LogEntry>,
+    // how many entries of the log made it to the storage
+    #[serde(skip)]
+    stored_changes: usize,
 }
 
 impl VotingSession {
-    pub fn new(session_id: u32, initiator_id: u32, initiator_name: String) -> VotingSession {
-        VotingSession {
-            id: session_id,
-            participants: vec![VotingParticipant::new(initiator_id, initiator_name)],
-            current_issue: VotingIssue::new(None),
+    // a session nobody has joined yet
+    pub fn new(
+        session_id: SessionId,
+        invite_code: String,
+        outcome_strategy: OutcomeStrategyKind,
+        deck: Deck,
+        passphrase: Option<String>,
+    ) -> VotingSession {
+        let creation = LogEntry {
+            at: unix_timestamp(),
+            change: SessionChange::SessionCreated {
+                session_id,
+                invite_code,
+                outcome_strategy,
+                deck,
+                passphrase_hash: passphrase.as_deref().map(PassphraseHash::new),
+                issue: VotingIssue::new(None),
+            },
+        };
+        VotingSession::replay(&[creation]).expect("the log starts with the session's creation")
+    }
+
+    // the session as it is after the logged changes, `None` unless the log starts with its creation
+    pub fn replay(log: &[LogEntry]) -> Option<VotingSession> {
+        let (creation, changes) = log.split_first()?;
+        let mut session = match &creation.change {
+            SessionChange::SessionCreated {
+                session_id,
+                invite_code,
+                outcome_strategy,
+                deck,
+                passphrase_hash,
+                issue,
+            } => VotingSession {
+                id: *session_id,
+                invite_code: invite_code.clone(),
+                participants: Vec::new(),
+                current_issue: issue.clone(),
+                outcome_strategy: *outcome_strategy,
+                deck: deck.clone(),
+                history: Vec::new(),
+                facilitator: String::new(),
+                passphrase_hash: passphrase_hash.clone(),
+                banned_names: Vec::new(),
+                last_seq: 0,
+                recent_events: VecDeque::new(),
+                log: vec![creation.clone()],
+                stored_changes: 0,
+            },
+            _ => return None,
+        };
+        for entry in changes {
+            session.apply(entry);
+            session.log.push(entry.clone());
         }
+        Some(session)
     }
 
-    pub fn participant_names(&self) -> Vec<String> {
-        self.participants.iter().map(|p| p.name.clone()).collect()
+    // apply the change and append it to the log
+    pub fn record_change(&mut self, change: SessionChange) {
+        let entry = LogEntry {
+            at: unix_timestamp(),
+            change,
+        };
+        self.apply(&entry);
+        self.log.push(entry);
     }
 
-    pub fn participant_ids(&self) -> Vec<u32> {
-        self.participants.iter().map(|p| p.id.clone()).collect()
+    // this is the only place where a session changes after it was created
+    fn apply(&mut self, entry: &LogEntry) {
+        match &entry.change {
+            SessionChange::SessionCreated { .. } => {
+                println!("Session {} can't be created a second time", self.id);
+            }
+            SessionChange::ParticipantJoined {
+                participant_id,
+                participant_name,
+                role,
+                rejoin_token,
+            } => {
+                self.participants.push(VotingParticipant::new(
+                    *participant_id,
+                    participant_name.clone(),
+                    *role,
+                    rejoin_token.clone(),
+                ));
+                self.ensure_facilitator();
+            }
+            SessionChange::ParticipantAway { participant_name } => {
+                if let Some(participant) = self.participant_mut(participant_name) {
+                    participant.away_since = Some(entry.at);
+                }
+            }
+            SessionChange::ParticipantReturned {
+                participant_id,
+                participant_name,
+            } => {
+                if let Some(participant) = self.participant_mut(participant_name) {
+                    participant.id = *participant_id;
+                    participant.away_since = None;
+                }
+            }
+            SessionChange::ParticipantLeft { participant_name } => {
+                self.participants.retain(|p| &p.name != participant_name);
+                self.ensure_facilitator();
+            }
+            SessionChange::ParticipantRenamed {
+                old_participant_name,
+                participant_name,
+            } => self.rename_participant(old_participant_name, participant_name),
+            SessionChange::ParticipantKicked {
+                participant_name,
+                ban,
+            } => {
+                if *ban && !self.banned_names.contains(participant_name) {
+                    self.banned_names.push(participant_name.clone());
+                }
+                // the vote of someone who was kicked only counts if it was already revealed
+                if self.current_issue.state != VotingState::Closing {
+                    self.current_issue.votes.remove(participant_name);
+                }
+            }
+            SessionChange::FacilitatorChanged { participant_name } => {
+                self.facilitator = participant_name.clone();
+            }
+            SessionChange::IssueChanged { issue } => self.change_issue(issue.clone(), entry.at),
+            SessionChange::VotingStarted { .. } => self.current_issue.state = VotingState::Voting,
+            SessionChange::NewRoundStarted { .. } => self.current_issue.start_new_round(),
+            SessionChange::IssueReopened { keep_votes, .. } => {
+                self.current_issue.reopen(*keep_votes)
+            }
+            SessionChange::VoteCast {
+                participant_name,
+                vote,
+                ..
+            } => {
+                self.current_issue
+                    .votes
+                    .insert(participant_name.clone(), vote.clone());
+            }
+            SessionChange::VoteRetracted {
+                participant_name, ..
+            } => {
+                self.current_issue.votes.remove(participant_name);
+            }
+            SessionChange::VotesRevealed { outcome, .. } => {
+                self.current_issue.outcome = Some(outcome.clone());
+                self.current_issue.state = VotingState::Closing;
+                self.current_issue.revealed_at = Some(entry.at);
+            }
+        }
     }
 
-    pub fn all_votes_cast(&self) -> bool {
-        self.participants.iter().all(|p| self.current_issue.votes.contains_key(p.name.as_str()))
+    fn participant_mut(&mut self, participant_name: &str) -> Option<&mut VotingParticipant> {
+        self.participants.iter_mut().find(|p| p.name == participant_name)
     }
-}
 
-impl Clone for VotingSession {
-    fn clone(&self) -> Self {
-        VotingSession {
-            id: self.id,
-            current_issue: self.current_issue.clone(),
-            participants: self.participants.clone(),
+    pub fn is_facilitator(&self, participant_id: u32) -> bool {
+        self.participants
+            .iter()
+            .any(|p| p.id == participant_id && p.name == self.facilitator)
+    }
+
+    // if the facilitator isn't around, the longest-serving participant takes over
+    fn ensure_facilitator(&mut self) {
+        if self.participants.iter().any(|p| p.name == self.facilitator) {
+            return;
+        }
+        if let Some(p) = self.participants.first() {
+            self.facilitator = p.name.clone();
         }
     }
-}
 
-pub struct Server {
-    sessions: HashMap<u32, VotingSession>,
-    timeout_sessions: HashMap<u32, std::time::Instant>,
-    clients: HashMap<u32, Recipient<PokerMessage>>,
-}
+    // replace the current issue, keeping the previous one in the history if it was revealed
+    fn change_issue(&mut self, issue: VotingIssue, closed_at: u64) {
+        let mut previous_issue = std::mem::replace(&mut self.current_issue, issue);
+        if previous_issue.state == VotingState::Closing {
+            previous_issue.closed_at = Some(closed_at);
+            self.history.push(previous_issue);
+        }
+    }
+
+    // change the name of a participant everywhere it's used
+    fn rename_participant(&mut self, old_name: &str, new_name: &str) {
+        if let Some(participant) = self.participant_mut(old_name) {
+            participant.name = new_name.to_string();
+            if self.facilitator == old_name {
+                self.facilitator = new_name.to_string();
+            }
+            for issue in self.history.iter_mut().chain(std::iter::once(&mut self.current_issue)) {
+                issue.rename_voter(old_name, new_name);
+            }
+        }
+    }
+
+    // number the message as the next event of the session and keep it around for a while
+    pub fn record_event(&mut self, message: PokerMessage) -> SessionEvent {
+        self.last_seq += 1;
+        let event = SessionEvent {
+            seq: self.last_seq,
+            message,
+        };
+        if self.recent_events.len() == MAX_RECENT_EVENTS {
+            self.recent_events.pop_front();
+        }
+        self.recent_events.push_back(event.clone());
+        event
+    }
+
+    // the events after the given one, `None` if not all of them are kept anymore
+    pub fn events_since(&self, seq: SequenceNumber) -> Option<Vec<SessionEvent>> {
+        let oldest_seq = self.last_seq + 1 - self.recent_events.len() as SequenceNumber;
+        if seq > self.last_seq || seq + 1 < oldest_seq {
+            return None;
+        }
+        Some(
+            self.recent_events
+                .iter()
+                .filter(|event| event.seq > seq)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    pub fn participant_names(&self) -> Vec<String> {
+        self.participants.iter().map(|p| p.name.clone()).collect()
+    }
+
+    // whether anyone ever joined the session, even if they left again
+    pub fn was_joined(&self) -> bool {
+        self.log
+            .iter()
+            .any(|entry| matches!(entry.change, SessionChange::ParticipantJoined { .. }))
+    }
+
+    // names of participants who left still appear next to their votes
+    pub fn is_name_in_use(&self, name: &str) -> bool {
+        self.participants.iter().any(|p| p.name == name)
+            || self
+                .history
+                .iter()
+                .chain(std::iter::once(&self.current_issue))
+                .any(|issue| issue.has_voter(name))
+    }
+
+    pub fn observer_names(&self) -> Vec<String> {
+        self.participants
+            .iter()
+            .filter(|p| !p.is_voter())
+            .map(|p| p.name.clone())
+            .collect()
+    }
+
+    pub fn away_participant_names(&self) -> Vec<String> {
+        self.participants
+            .iter()
+            .filter(|p| p.is_away())
+            .map(|p| p.name.clone())
+            .collect()
+    }
+
+    // ids of everyone who is connected and can receive messages
+    pub fn participant_ids(&self) -> Vec<u32> {
+        self.participants
+            .iter()
+            .filter(|p| !p.is_away())
+            .map(|p| p.id)
+            .collect()
+    }
+
+    // the state of the session as seen by the given participant
+    pub fn session_info(&self, participant_name: &String) -> PokerMessage {
+        PokerMessage::SessionInfoResponse {
+            session_id: self.id,
+            invite_code: self.invite_code.clone(),
+            current_issue: self.current_issue.clone_blinded(Some(participant_name)),
+            current_participants: self.participant_names(),
+            observers: self.observer_names(),
+            outcome_strategy: self.outcome_strategy,
+            deck: self.deck.cards(),
+            facilitator: self.facilitator.clone(),
+            away_participants: self.away_participant_names(),
+            rejoin_token: self
+                .participants
+                .iter()
+                .find(|p| &p.name == participant_name)
+                .map(|p| p.rejoin_token.clone())
+                .unwrap_or_default(),
+            seq: self.last_seq,
+        }
+    }
+
+    pub fn overview(&self) -> SessionOverview {
+        SessionOverview {
+            session_id: self.id,
+            invite_code: self.invite_code.clone(),
+            outcome_strategy: self.outcome_strategy,
+            deck: self.deck.cards(),
+            facilitator: self.facilitator.clone(),
+            participants: self.participant_names(),
+            observers: self.observer_names(),
+            away_participants: self.away_participant_names(),
+            current_issue: self.current_issue.clone_blinded(None),
+            has_passphrase: self.passphrase_hash.is_some(),
+        }
+    }
+
+    // whether a request about the given issue can be handled while it's in the given state
+    pub fn check_current_issue(&self, issue_id: u32, state: VotingState) -> Result<(), ErrorCode> {
+        if self.current_issue.id != issue_id {
+            Err(ErrorCode::IssueMismatch)
+        } else if self.current_issue.state != state {
+            Err(ErrorCode::InvalidVotingState)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn all_votes_cast(&self) -> bool {
+        self.missing_voters().is_empty()
+    }
+
+    pub fn missing_voters(&self) -> Vec<String> {
+        self.participants
+            .iter()
+            .filter(|p| p.is_voter() && !self.current_issue.votes.contains_key(p.name.as_str()))
+            .map(|p| p.name.clone())
+            .collect()
+    }
+}
+
+impl Clone for VotingSession {
+    fn clone(&self) -> Self {
+        VotingSession {
+            id: self.id,
+            invite_code: self.invite_code.clone(),
+            current_issue: self.current_issue.clone(),
+            participants: self.participants.clone(),
+            outcome_strategy: self.outcome_strategy,
+            deck: self.deck.clone(),
+            history: self.history.clone(),
+            facilitator: self.facilitator.clone(),
+            passphrase_hash: self.passphrase_hash.clone(),
+            banned_names: self.banned_names.clone(),
+            last_seq: self.last_seq,
+            recent_events: self.recent_events.clone(),
+            log: self.log.clone(),
+            stored_changes: self.stored_changes,
+        }
+    }
+}
+
+pub struct Server {
+    sessions: HashMap<SessionId, VotingSession>,
+    // the session each invite code belongs to
+    invite_codes: HashMap<String, SessionId>,
+    // when each session without participants times out
+    timeout_sessions: HashMap<SessionId, std::time::Instant>,
+    // when each peer address recently tried a wrong passphrase
+    failed_passphrase_attempts: HashMap<String, Vec<Instant>>,
+    // when each peer address recently tried an invite code that doesn't belong to any session
+    failed_invite_code_attempts: HashMap<String, Vec<Instant>>,
+    clients: HashMap<u32, Recipient<ServerMessage>>,
+    // the address each client connects from
+    peer_addresses: HashMap<u32, String>,
+    storage: Box<dyn SessionStorage>,
+    // how long participants keep their seat after their connection dropped
+    reconnect_grace_period: Duration,
+    // the request being handled right now, if its sender wants to hear back about it
+    pending_request: Option<PendingRequest>,
+}
+
+struct PendingRequest {
+    participant_id: u32,
+    request_id: RequestId,
+    // responses are sent through `&self`, so this has to be set from there
+    rejected: Cell<bool>,
+}
 
 impl Server {
-    pub fn new() -> Server {
-        Server {
+    pub fn new(storage: Box<dyn SessionStorage>, reconnect_grace_period: Duration) -> Server {
+        let mut server = Server {
             sessions: HashMap::new(),
+            invite_codes: HashMap::new(),
             clients: HashMap::new(),
+            peer_addresses: HashMap::new(),
             timeout_sessions: HashMap::new(),
+            failed_passphrase_attempts: HashMap::new(),
+            failed_invite_code_attempts: HashMap::new(),
+            storage,
+            reconnect_grace_period,
+            pending_request: None,
+        };
+        server.restore_sessions();
+        server
+    }
+
+    // nobody is connected to the restored sessions yet, so everyone in them is away until they
+    // rejoin with their rejoin token, like after any other dropped connection
+    fn restore_sessions(&mut self) {
+        match self.storage.load_all() {
+            Ok(logs) => {
+                for stored in logs {
+                    let mut session = match VotingSession::replay(&stored.entries) {
+                        Some(session) => session,
+                        None => {
+                            println!("Skipping a stored log that doesn't start with a session");
+                            continue;
+                        }
+                    };
+                    session.last_seq = stored.last_seq;
+                    session.stored_changes = session.log.len();
+                    let present_names: Vec<String> = session
+                        .participants
+                        .iter()
+                        .filter(|p| !p.is_away())
+                        .map(|p| p.name.clone())
+                        .collect();
+                    for participant_name in present_names {
+                        session.record_change(SessionChange::ParticipantAway { participant_name });
+                    }
+                    if session.participants.is_empty() {
+                        let timeout = if session.was_joined() {
+                            SESSION_TIMEOUT
+                        } else {
+                            UNJOINED_SESSION_TIMEOUT
+                        };
+                        self.timeout_sessions.insert(session.id, Instant::now() + timeout);
+                    }
+                    let session_id = session.id;
+                    self.invite_codes.insert(session.invite_code.clone(), session_id);
+                    self.sessions.insert(session_id, session);
+                    self.persist_session(session_id);
+                }
+                println!("Restored {} sessions from storage", self.sessions.len());
+            }
+            Err(e) => println!("Failed to restore sessions from storage: {}", e),
         }
     }
 
-    fn create_session(&mut self, initiator_id: u32, initiator_name: String) -> VotingSession {
-        let session_id = generate_random_id();
-        let session = VotingSession::new(session_id, initiator_id, initiator_name);
-        self.sessions.insert(session_id, session.clone());
-        session
+    // append the changes the storage hasn't seen yet to the session's stored log
+    fn persist_session(&mut self, session_id: SessionId) {
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            let changes = &session.log[session.stored_changes..];
+            if changes.is_empty() {
+                return;
+            }
+            match self.storage.append(session_id, changes, session.last_seq) {
+                Ok(()) => session.stored_changes = session.log.len(),
+                Err(e) => println!("Failed to store session {}: {}", session_id, e),
+            }
+        }
+    }
+
+    // a session nobody has joined yet
+    fn create_session(
+        &mut self,
+        outcome_strategy: OutcomeStrategyKind,
+        deck: Deck,
+        passphrase: Option<String>,
+    ) -> SessionId {
+        // ids are far too long to ever collide, but invite codes might
+        let mut session_id = SessionId::generate();
+        while session_id.is_none() || self.sessions.contains_key(&session_id) {
+            session_id = SessionId::generate();
+        }
+        let mut invite_code = generate_invite_code();
+        while self.invite_codes.contains_key(&invite_code) {
+            invite_code = generate_invite_code();
+        }
+        self.invite_codes.insert(invite_code.clone(), session_id);
+        let session = VotingSession::new(
+            session_id,
+            invite_code,
+            outcome_strategy,
+            deck,
+            passphrase.filter(|passphrase| !passphrase.is_empty()),
+        );
+        self.sessions.insert(session_id, session);
+        session_id
+    }
+
+    // where the client connects from, which failed attempts are counted against
+    fn peer_address(&self, participant_id: u32) -> String {
+        self.peer_addresses
+            .get(&participant_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // the session the invite code belongs to; guessing codes gets an address locked out quickly
+    fn resolve_invite_code(
+        &mut self,
+        participant_id: u32,
+        invite_code: &str,
+    ) -> Result<SessionId, SessionJoinError> {
+        let peer_address = self.peer_address(participant_id);
+        let attempts = self
+            .failed_invite_code_attempts
+            .entry(peer_address)
+            .or_default();
+        attempts.retain(|attempt| attempt.elapsed() < INVITE_CODE_ATTEMPT_WINDOW);
+        if attempts.len() >= MAX_INVITE_CODE_ATTEMPTS {
+            return Err(SessionJoinError::TooManyAttempts);
+        }
+        match self.invite_codes.get(&normalize_invite_code(invite_code)) {
+            Some(&session_id) => Ok(session_id),
+            None => {
+                attempts.push(Instant::now());
+                Err(SessionJoinError::UnknownSession)
+            }
+        }
+    }
+
+    // announce the event to all of the given participants
+    fn broadcast(&self, participant_ids: &[u32], event: SessionEvent) {
+        participant_ids
+            .iter()
+            .for_each(|&p| self.send_event(p, event.seq, event.message.clone()));
+    }
+
+    // dispatch a message that isn't an event of a session to all of the given participants
+    fn broadcast_message(&self, participant_ids: &[u32], message: PokerMessage) {
+        participant_ids
+            .iter()
+            .for_each(|&p| self.send_message(p, message.clone()));
+    }
+
+    // let the participant know why their request had no effect
+    fn reject(&self, participant_id: u32, request: &str, code: ErrorCode) {
+        self.send_message(
+            participant_id,
+            PokerMessage::ErrorResponse {
+                code,
+                request: Some(request.to_string()),
+                request_id: None,
+            },
+        );
+    }
+
+    // older clients are also sent the deprecated `SessionJoinErrorResponse`
+    fn reject_join(
+        &self,
+        participant_id: u32,
+        request: &str,
+        session_id: SessionId,
+        error: SessionJoinError,
+    ) {
+        self.reject(participant_id, request, error.into());
+        self.send_message(
+            participant_id,
+            PokerMessage::SessionJoinErrorResponse { session_id, error },
+        );
     }
 
     // dispatch the message to the right participant
-    fn send_message(&self, participant_id: u32, message: PokerMessage) {
+    fn send_message(&self, participant_id: u32, mut message: PokerMessage) {
+        if let Some(pending) = &self.pending_request {
+            if pending.participant_id == participant_id
+                && message.attach_request_id(pending.request_id)
+            {
+                pending.rejected.set(true);
+            }
+        }
+        self.dispatch(participant_id, ServerMessage { message, seq: None });
+    }
+
+    // dispatch an event of the participant's session to them
+    fn send_event(&self, participant_id: u32, seq: SequenceNumber, message: PokerMessage) {
+        self.dispatch(
+            participant_id,
+            ServerMessage {
+                message,
+                seq: Some(seq),
+            },
+        );
+    }
+
+    fn dispatch(&self, participant_id: u32, message: ServerMessage) {
         if let Some((_, recipient)) = self
             .clients
             .iter()
@@ -296,6 +1492,7 @@ impl Actor for Server {
 
     fn started(&mut self, ctx: &mut Self::Context) {
         self.start_session_timeout_check(ctx);
+        self.start_restored_grace_periods(ctx);
     }
 }
 
@@ -304,7 +1501,8 @@ impl Handler<Connect> for Server {
 
     fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> u32 {
         let client_id = generate_random_id();
-        self.clients.insert(client_id.clone(), msg.addr);
+        self.clients.insert(client_id, msg.addr);
+        self.peer_addresses.insert(client_id, msg.peer_address);
         client_id
     }
 }
@@ -312,35 +1510,31 @@ impl Handler<Connect> for Server {
 impl Handler<Disconnect> for Server {
     type Result = ();
 
-    fn handle(&mut self, msg: Disconnect, _: &mut Self::Context) {
+    // participants keep their seat for a grace period, in case they're only reconnecting
+    fn handle(&mut self, msg: Disconnect, ctx: &mut Self::Context) {
         if let Some(session) = self.sessions.get_mut(&msg.session_id) {
-            if session.participants.len() == 1 {
-                session.participants.clear();
-                self.timeout_sessions
-                    .insert(session.id, std::time::Instant::now());
+            if let Some(participant) = session
+                .participants
+                .iter()
+                .find(|p| p.id == msg.participant_id)
+            {
+                let participant_name = participant.name.clone();
+                session.record_change(SessionChange::ParticipantAway {
+                    participant_name: participant_name.clone(),
+                });
+                let event = session
+                    .record_event(PokerMessage::ParticipantAwayAnnouncement { participant_name });
+                let participant_ids = session.participant_ids();
+                self.broadcast(&participant_ids, event);
+                let (session_id, participant_id) = (msg.session_id, msg.participant_id);
+                ctx.run_later(self.reconnect_grace_period, move |act, _| {
+                    act.remove_participant_if_away(session_id, participant_id);
+                });
             } else {
-                // TODO: it should be perfectly acceptable to factor this out but it does not work
-                if let Some(pos) = session
-                    .participants
-                    .iter()
-                    .position(|p| p.id == msg.participant_id)
-                {
-                    let removed = session.participants.remove(pos);
-                    let participant_ids: Vec<u32> =
-                        session.participants.iter().map(|p| p.id).collect();
-                    participant_ids.iter().for_each(|p| {
-                        let message = PokerMessage::ParticipantLeaveAnnouncement {
-                            participant_name: removed.name.clone(),
-                        };
-                        self.send_message(*p, message);
-                    });
-                } else {
-                    println!("For some reason the participant wasn't in the expected session?!");
-                }
+                println!("For some reason the participant wasn't in the expected session?!");
             }
-            self.reveal_if_everyone_voted(msg.session_id);
         } else {
-            if msg.session_id > 0 {
+            if !msg.session_id.is_none() {
                 println!(
                     "Client is trying to leave non-existing session {}",
                     msg.session_id
@@ -348,145 +1542,759 @@ impl Handler<Disconnect> for Server {
             }
         }
 
+        self.persist_session(msg.session_id);
         self.clients.remove(&msg.participant_id);
+        self.peer_addresses.remove(&msg.participant_id);
+    }
+}
+
+impl Handler<ExportSession> for Server {
+    type Result = Option<SessionExport>;
+
+    fn handle(&mut self, msg: ExportSession, _: &mut Self::Context) -> Self::Result {
+        let session = self.sessions.get(&msg.session_id)?;
+        // the current issue only counts as estimated once its votes were revealed
+        let current_issue = Some(&session.current_issue)
+            .filter(|issue| issue.state == VotingState::Closing);
+        Some(SessionExport {
+            session_id: session.id,
+            issues: session
+                .history
+                .iter()
+                .chain(current_issue)
+                .map(|issue| issue.export())
+                .collect(),
+        })
+    }
+}
+
+impl Handler<ListSessions> for Server {
+    type Result = MessageResult<ListSessions>;
+
+    fn handle(&mut self, _: ListSessions, _: &mut Self::Context) -> Self::Result {
+        MessageResult(self.sessions.values().map(VotingSession::overview).collect())
+    }
+}
+
+impl Handler<InspectSession> for Server {
+    type Result = Option<SessionOverview>;
+
+    fn handle(&mut self, msg: InspectSession, _: &mut Self::Context) -> Self::Result {
+        self.sessions.get(&msg.session_id).map(VotingSession::overview)
+    }
+}
+
+impl Handler<CreateSession> for Server {
+    type Result = MessageResult<CreateSession>;
+
+    // the session is set up ahead of the meeting, so it waits a while for someone to join
+    fn handle(&mut self, msg: CreateSession, _: &mut Self::Context) -> Self::Result {
+        let session_id = self.create_session(msg.outcome_strategy, msg.deck, msg.passphrase);
+        self.timeout_sessions
+            .insert(session_id, Instant::now() + UNJOINED_SESSION_TIMEOUT);
+        self.persist_session(session_id);
+        MessageResult(self.sessions[&session_id].overview())
+    }
+}
+
+impl Handler<ChangeIssue> for Server {
+    type Result = Option<VotingIssue>;
+
+    fn handle(&mut self, msg: ChangeIssue, _: &mut Self::Context) -> Self::Result {
+        self.change_topic(msg.session_id, msg.trello_card);
+        self.persist_session(msg.session_id);
+        let session = self.sessions.get(&msg.session_id)?;
+        Some(session.current_issue.clone_blinded(None))
+    }
+}
+
+impl Handler<CloseSession> for Server {
+    type Result = bool;
+
+    // everyone who is connected to the session gets to know why they were dropped from it
+    fn handle(&mut self, msg: CloseSession, _: &mut Self::Context) -> Self::Result {
+        let session_id = msg.session_id;
+        let participant_ids = match self.sessions.get(&session_id) {
+            Some(session) => session.participant_ids(),
+            None => return false,
+        };
+        self.broadcast_message(
+            &participant_ids,
+            PokerMessage::SessionClosedAnnouncement { session_id },
+        );
+        self.remove_session(session_id);
+        true
+    }
+}
+
+impl Handler<ExportSessionLog> for Server {
+    type Result = Option<Vec<LogEntry>>;
+
+    fn handle(&mut self, msg: ExportSessionLog, _: &mut Self::Context) -> Self::Result {
+        let session = self.sessions.get(&msg.session_id)?;
+        Some(redacted_log(&session.log))
+    }
+}
+
+impl Handler<ClientRequest> for Server {
+    type Result = ();
+
+    // requests with an id are answered with either an acknowledgement or a rejection
+    fn handle(&mut self, request: ClientRequest, ctx: &mut Context<Self>) {
+        self.pending_request = request.request_id.map(|request_id| PendingRequest {
+            participant_id: request.participant_id,
+            request_id,
+            rejected: Cell::new(false),
+        });
+        Handler::<PokerMessage>::handle(self, request.message, ctx);
+        if let Some(pending) = self.pending_request.take() {
+            if !pending.rejected.get() {
+                self.send_message(
+                    pending.participant_id,
+                    PokerMessage::AckResponse {
+                        request_id: pending.request_id,
+                    },
+                );
+            }
+        }
     }
 }
 
 impl Handler<PokerMessage> for Server {
     type Result = ();
 
-    fn handle(&mut self, msg: PokerMessage, _: &mut Context<Self>) {
+    fn handle(&mut self, msg: PokerMessage, ctx: &mut Context<Self>) {
+        let session_id = msg.session_id();
         match msg {
             PokerMessage::CreateSessionRequest {
                 participant_id,
                 participant_name,
+                outcome_strategy,
+                deck,
+                role,
+                passphrase,
             } => {
-                self.handle_create_session_request(participant_id, participant_name);
+                self.handle_create_session_request(
+                    participant_id,
+                    participant_name,
+                    role,
+                    outcome_strategy,
+                    deck,
+                    passphrase,
+                );
             }
             PokerMessage::JoinSessionRequest {
                 participant_id,
                 participant_name,
                 session_id,
-            } => self.handle_join_session_request(session_id, participant_id, participant_name),
+                invite_code,
+                role,
+                rejoin_token,
+                passphrase,
+            } => {
+                let session_id = match invite_code {
+                    Some(invite_code) => {
+                        match self.resolve_invite_code(participant_id, &invite_code) {
+                            Ok(session_id) => session_id,
+                            Err(error) => {
+                                self.reject_join(
+                                    participant_id,
+                                    "JoinSessionRequest",
+                                    session_id,
+                                    error,
+                                );
+                                return;
+                            }
+                        }
+                    }
+                    None => session_id,
+                };
+                self.handle_join_session_request(
+                    session_id,
+                    participant_id,
+                    participant_name,
+                    role,
+                    rejoin_token,
+                    passphrase,
+                );
+                self.persist_session(session_id);
+            }
+            PokerMessage::FacilitatorTransferRequest {
+                session_id,
+                participant_id,
+                participant_name,
+            } => self.handle_facilitator_transfer_request(
+                session_id,
+                participant_id,
+                participant_name,
+            ),
+            PokerMessage::ParticipantRenameRequest {
+                session_id,
+                participant_id,
+                participant_name,
+            } => self.handle_participant_rename_request(
+                session_id,
+                participant_id,
+                participant_name,
+            ),
+            PokerMessage::ParticipantKickRequest {
+                session_id,
+                participant_id,
+                participant_name,
+                ban,
+                reason,
+            } => self.handle_participant_kick_request(
+                session_id,
+                participant_id,
+                participant_name,
+                ban,
+                reason,
+            ),
             PokerMessage::TopicChangeRequest {
                 session_id,
                 participant_id,
                 trello_card,
             } => self.handle_topic_change_request(session_id, participant_id, trello_card),
+            PokerMessage::IssueHistoryRequest {
+                session_id,
+                participant_id,
+            } => self.handle_issue_history_request(session_id, participant_id),
+            PokerMessage::ResyncRequest {
+                session_id,
+                participant_id,
+                seq,
+            } => self.handle_resync_request(session_id, participant_id, seq),
+            PokerMessage::VotingStartRequest {
+                session_id,
+                participant_id,
+                issue_id,
+                countdown_seconds,
+            } => self.handle_voting_start_request(
+                session_id,
+                issue_id,
+                participant_id,
+                countdown_seconds,
+                ctx,
+            ),
+            PokerMessage::NewRoundRequest {
+                session_id,
+                participant_id,
+                issue_id,
+            } => self.handle_new_round_request(session_id, issue_id, participant_id),
+            PokerMessage::IssueReopenRequest {
+                session_id,
+                participant_id,
+                issue_id,
+                keep_votes,
+            } => self.handle_issue_reopen_request(session_id, issue_id, participant_id, keep_votes),
             PokerMessage::VoteRequest {
                 session_id,
                 participant_id,
                 issue_id,
                 vote,
             } => self.handle_vote_request(session_id, issue_id, participant_id, vote),
+            PokerMessage::VoteRetractionRequest {
+                session_id,
+                participant_id,
+                issue_id,
+            } => self.handle_vote_retraction_request(session_id, issue_id, participant_id),
+            PokerMessage::VoteRevelationRequest {
+                session_id,
+                participant_id,
+                issue_id,
+            } => self.handle_vote_revelation_request(session_id, issue_id, participant_id),
             _ => {
                 println!("Message not handled: {:?}", msg);
             }
         }
+        if let Some(session_id) = session_id {
+            self.persist_session(session_id);
+        }
     }
 }
 
 const SESSION_TIMEOUT: Duration = Duration::from_secs(20);
+// how long a session nobody has joined yet is kept
+const UNJOINED_SESSION_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+const MAX_VOTING_COUNTDOWN_SECONDS: u64 = 60;
 const SESSION_TIMEOUT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_PASSPHRASE_ATTEMPTS: usize = 5;
+const PASSPHRASE_ATTEMPT_WINDOW: Duration = Duration::from_secs(60);
+const MAX_INVITE_CODE_ATTEMPTS: usize = 5;
+const INVITE_CODE_ATTEMPT_WINDOW: Duration = Duration::from_secs(60);
 
 impl Server {
     fn start_session_timeout_check(&self, ctx: &mut Context<Server>) {
         ctx.run_interval(SESSION_TIMEOUT_CHECK_INTERVAL, |act, _| {
             let mut sessions_to_delete = Vec::new();
             act.timeout_sessions
-                .retain(|session_id, last_seen| -> bool {
-                    if Instant::now().duration_since(*last_seen) > SESSION_TIMEOUT {
-                        sessions_to_delete.push(session_id.clone());
+                .retain(|session_id, timeout| -> bool {
+                    if Instant::now() > *timeout {
+                        sessions_to_delete.push(*session_id);
                         false
                     } else {
                         true
                     }
                 });
-            act.sessions
-                .retain(|session_id, _| -> bool { !sessions_to_delete.contains(&session_id) });
+            for session_id in sessions_to_delete {
+                act.remove_session(session_id);
+            }
+            act.failed_passphrase_attempts.retain(|_, attempts| {
+                attempts.retain(|attempt| attempt.elapsed() < PASSPHRASE_ATTEMPT_WINDOW);
+                !attempts.is_empty()
+            });
+            act.failed_invite_code_attempts.retain(|_, attempts| {
+                attempts.retain(|attempt| attempt.elapsed() < INVITE_CODE_ATTEMPT_WINDOW);
+                !attempts.is_empty()
+            });
         });
     }
 
-    fn handle_create_session_request(&mut self, participant_id: u32, participant_name: String) {
-        let session = self.create_session(participant_id, participant_name.clone());
-        let current_participant_names = session.participant_names();
-        self.send_message(
-            participant_id,
-            PokerMessage::SessionInfoResponse {
-                session_id: session.id,
-                current_issue: session.current_issue.clone_blinded(Some(&participant_name)),
-                current_participants: current_participant_names,
-            },
-        );
+    // forget everything about the session, including its copy in the storage
+    fn remove_session(&mut self, session_id: SessionId) {
+        if let Some(session) = self.sessions.remove(&session_id) {
+            self.invite_codes.remove(&session.invite_code);
+        }
+        self.timeout_sessions.remove(&session_id);
+        if let Err(e) = self.storage.remove(session_id) {
+            println!("Failed to remove session {} from storage: {}", session_id, e);
+        }
     }
 
-    fn handle_join_session_request(
-        &mut self,
-        session_id: u32,
-        participant_id: u32,
-        participant_name: String,
-    ) {
-        if let Some(session) = self.sessions.get_mut(&session_id) {
-            // if someone joins a session that was previously set to time out, it needs to be kept alive
-            self.timeout_sessions.remove(&session_id);
+    // the participants of restored sessions keep their seats for as long as after a disconnect
+    fn start_restored_grace_periods(&self, ctx: &mut Context<Server>) {
+        for session in self.sessions.values() {
+            for participant in session.participants.iter().filter(|p| p.is_away()) {
+                let (session_id, participant_id) = (session.id, participant.id);
+                ctx.run_later(self.reconnect_grace_period, move |act, _| {
+                    act.remove_participant_if_away(session_id, participant_id);
+                });
+            }
+        }
+    }
 
-            // now check that the name hasn't already been taken
-            if session
+    // participants who reconnected in time got a new id, so they won't be found anymore
+    fn remove_participant_if_away(&mut self, session_id: SessionId, participant_id: u32) {
+        let is_away = self.sessions.get(&session_id).map_or(false, |session| {
+            session
                 .participants
                 .iter()
-                .any(|p| p.name == participant_name)
-            {
-                self.send_message(
+                .any(|p| p.id == participant_id && p.is_away())
+        });
+        if is_away {
+            self.remove_participant(session_id, participant_id);
+        }
+    }
+
+    fn remove_participant(&mut self, session_id: SessionId, participant_id: u32) {
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            if let Some(removed) = session
+                .participants
+                .iter()
+                .find(|p| p.id == participant_id)
+            {
+                let participant_name = removed.name.clone();
+                let facilitator = session.facilitator.clone();
+                session.record_change(SessionChange::ParticipantLeft {
+                    participant_name: participant_name.clone(),
+                });
+                if session.participants.is_empty() {
+                    self.timeout_sessions
+                        .insert(session_id, Instant::now() + SESSION_TIMEOUT);
+                }
+                let leave_event = session
+                    .record_event(PokerMessage::ParticipantLeaveAnnouncement { participant_name });
+                let facilitator_event = if session.facilitator != facilitator {
+                    Some(session.record_event(PokerMessage::FacilitatorAnnouncement {
+                        participant_name: session.facilitator.clone(),
+                    }))
+                } else {
+                    None
+                };
+                let participant_ids = session.participant_ids();
+                self.broadcast(&participant_ids, leave_event);
+                if let Some(event) = facilitator_event {
+                    self.broadcast(&participant_ids, event);
+                }
+                self.reveal_if_everyone_voted(session_id);
+                self.persist_session(session_id);
+            }
+        }
+    }
+
+    fn handle_create_session_request(
+        &mut self,
+        participant_id: u32,
+        participant_name: String,
+        role: ParticipantRole,
+        outcome_strategy: OutcomeStrategyKind,
+        deck: Deck,
+        passphrase: Option<String>,
+    ) {
+        if !deck.is_valid() {
+            self.reject_join(
+                participant_id,
+                "CreateSessionRequest",
+                SessionId::default(),
+                SessionJoinError::InvalidDeck,
+            );
+            return;
+        }
+        let session_id = self.create_session(outcome_strategy, deck, passphrase);
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            session.record_change(SessionChange::ParticipantJoined {
+                participant_id,
+                participant_name: participant_name.clone(),
+                role,
+                rejoin_token: generate_token(),
+            });
+            let session_info = session.session_info(&participant_name);
+            self.send_message(participant_id, session_info);
+        }
+        self.persist_session(session_id);
+    }
+
+    fn handle_join_session_request(
+        &mut self,
+        session_id: SessionId,
+        participant_id: u32,
+        participant_name: String,
+        role: ParticipantRole,
+        rejoin_token: Option<String>,
+        passphrase: Option<String>,
+    ) {
+        if let Err(error) = self.check_passphrase(
+            session_id,
+            participant_id,
+            &participant_name,
+            rejoin_token.as_ref(),
+            passphrase.as_ref(),
+        ) {
+            self.reject_join(participant_id, "JoinSessionRequest", session_id, error);
+            return;
+        }
+
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            if session.banned_names.contains(&participant_name) {
+                self.reject_join(
                     participant_id,
-                    PokerMessage::SessionJoinErrorResponse {
-                        session_id,
-                        error: SessionJoinError::ParticipantNameTaken,
-                    },
+                    "JoinSessionRequest",
+                    session_id,
+                    SessionJoinError::ParticipantNameBanned,
+                );
+                return;
+            }
+
+            // only the holder of the rejoin token can take a seat back, be it after their
+            // connection dropped or even before the server noticed the old connection died
+            if let Some(participant) = session.participants.iter().find(|p| {
+                p.name == participant_name && rejoin_token.as_ref() == Some(&p.rejoin_token)
+            }) {
+                let (stale_id, was_away) = (participant.id, participant.is_away());
+                session.record_change(SessionChange::ParticipantReturned {
+                    participant_id,
+                    participant_name: participant_name.clone(),
+                });
+                if stale_id != participant_id {
+                    self.clients.remove(&stale_id);
+                }
+                let other_participant_ids: Vec<u32> = session
+                    .participant_ids()
+                    .into_iter()
+                    .filter(|&p| p != participant_id)
+                    .collect();
+                // the state sent to the participant already reflects their return
+                let return_event = if was_away {
+                    Some(session.record_event(PokerMessage::ParticipantReturnAnnouncement {
+                        participant_name: participant_name.clone(),
+                    }))
+                } else {
+                    None
+                };
+                let session_info = session.session_info(&participant_name);
+                self.send_message(participant_id, session_info);
+                if let Some(event) = return_event {
+                    self.broadcast(&other_participant_ids, event);
+                }
+                return;
+            }
+
+            // now check that the name hasn't already been taken, not even by someone who left
+            // after voting, whose votes would otherwise be taken over along with their name
+            if session.is_name_in_use(&participant_name) {
+                self.reject_join(
+                    participant_id,
+                    "JoinSessionRequest",
+                    session_id,
+                    SessionJoinError::ParticipantNameTaken,
                 );
                 return;
             }
 
+            // if someone joins a session that was previously set to time out, it needs to be kept alive
+            self.timeout_sessions.remove(&session_id);
+
             // save the current participant list so we can notify them about someone joining
-            let current_participant_ids: Vec<u32> =
-                session.participants.iter().map(|p| p.id).collect();
+            let current_participant_ids = session.participant_ids();
             // add the new participant
-            session.participants.push(VotingParticipant::new(
+            let facilitator = session.facilitator.clone();
+            session.record_change(SessionChange::ParticipantJoined {
                 participant_id,
-                participant_name.clone(),
-            ));
-            // and once they were added, let them know that they successfully joined
-            let message = PokerMessage::SessionInfoResponse {
-                session_id: session.id,
-                current_issue: session.current_issue.clone_blinded(Some(&participant_name)),
-                current_participants: session.participant_names(),
+                participant_name: participant_name.clone(),
+                role,
+                rejoin_token: generate_token(),
+            });
+            let join_event = session.record_event(PokerMessage::ParticipantJoinAnnouncement {
+                participant_name: participant_name.clone(),
+                role,
+            });
+            let facilitator_event = if session.facilitator != facilitator {
+                Some(session.record_event(PokerMessage::FacilitatorAnnouncement {
+                    participant_name: participant_name.clone(),
+                }))
+            } else {
+                None
             };
-            self.send_message(participant_id, message);
+            // and once they were added, let them know that they successfully joined
+            let session_info = session.session_info(&participant_name);
+            self.send_message(participant_id, session_info);
             // notify everyone else about the new participant
-            current_participant_ids.iter().for_each(|participant_id| {
-                let message = PokerMessage::ParticipantJoinAnnouncement {
-                    participant_name: participant_name.clone(),
-                };
-                self.send_message(*participant_id, message);
-            });
+            self.broadcast(&current_participant_ids, join_event);
+            if let Some(event) = facilitator_event {
+                self.broadcast(&current_participant_ids, event);
+            }
         } else {
-            self.send_message(
+            self.reject_join(
                 participant_id,
-                PokerMessage::SessionJoinErrorResponse {
-                    session_id,
-                    error: SessionJoinError::UnknownSession,
-                },
+                "JoinSessionRequest",
+                session_id,
+                SessionJoinError::UnknownSession,
             );
         }
     }
 
+    // sessions with a passphrase only admit those who know it or who already hold a seat
+    // wrong passphrases are counted per peer address, so reconnecting doesn't start over
+    fn check_passphrase(
+        &mut self,
+        session_id: SessionId,
+        participant_id: u32,
+        participant_name: &str,
+        rejoin_token: Option<&String>,
+        passphrase: Option<&String>,
+    ) -> Result<(), SessionJoinError> {
+        let session = match self.sessions.get(&session_id) {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+        let passphrase_hash = match &session.passphrase_hash {
+            Some(passphrase_hash) => passphrase_hash,
+            None => return Ok(()),
+        };
+        if session
+            .participants
+            .iter()
+            .any(|p| p.name == participant_name && rejoin_token == Some(&p.rejoin_token))
+        {
+            return Ok(());
+        }
+
+        let peer_address = self.peer_address(participant_id);
+        let attempts = self
+            .failed_passphrase_attempts
+            .entry(peer_address)
+            .or_default();
+        attempts.retain(|attempt| attempt.elapsed() < PASSPHRASE_ATTEMPT_WINDOW);
+        if attempts.len() >= MAX_PASSPHRASE_ATTEMPTS {
+            return Err(SessionJoinError::TooManyAttempts);
+        }
+        if passphrase.map_or(false, |passphrase| passphrase_hash.matches(passphrase)) {
+            Ok(())
+        } else {
+            attempts.push(Instant::now());
+            Err(SessionJoinError::WrongPassphrase)
+        }
+    }
+
+    // only the facilitator may control the session; everyone else gets told off
+    fn authorize_facilitator(
+        &self,
+        session_id: SessionId,
+        participant_id: u32,
+        request: &str,
+    ) -> bool {
+        match self.sessions.get(&session_id) {
+            Some(session) if session.is_facilitator(participant_id) => true,
+            Some(session) if session.participants.iter().any(|p| p.id == participant_id) => {
+                self.reject(participant_id, request, ErrorCode::PermissionDenied);
+                // for older clients
+                self.send_message(
+                    participant_id,
+                    PokerMessage::PermissionDeniedResponse {
+                        session_id,
+                        request: request.to_string(),
+                    },
+                );
+                false
+            }
+            _ => {
+                self.reject(participant_id, request, ErrorCode::NotInSession);
+                false
+            }
+        }
+    }
+
+    fn handle_facilitator_transfer_request(
+        &mut self,
+        session_id: SessionId,
+        participant_id: u32,
+        participant_name: String,
+    ) {
+        if !self.authorize_facilitator(session_id, participant_id, "FacilitatorTransferRequest") {
+            return;
+        }
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            if !session.participants.iter().any(|p| p.name == participant_name) {
+                self.reject(
+                    participant_id,
+                    "FacilitatorTransferRequest",
+                    ErrorCode::UnknownParticipant,
+                );
+                return;
+            }
+            session.record_change(SessionChange::FacilitatorChanged {
+                participant_name: participant_name.clone(),
+            });
+            let event =
+                session.record_event(PokerMessage::FacilitatorAnnouncement { participant_name });
+            let participant_ids = session.participant_ids();
+            self.broadcast(&participant_ids, event);
+        }
+    }
+
+    fn handle_participant_rename_request(
+        &mut self,
+        session_id: SessionId,
+        participant_id: u32,
+        participant_name: String,
+    ) {
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            let old_participant_name = match session
+                .participants
+                .iter()
+                .find(|p| p.id == participant_id)
+            {
+                Some(participant) if participant.name != participant_name => {
+                    participant.name.clone()
+                }
+                Some(_) => return,
+                None => {
+                    self.reject(
+                        participant_id,
+                        "ParticipantRenameRequest",
+                        ErrorCode::NotInSession,
+                    );
+                    return;
+                }
+            };
+            // the same rules apply as for names of participants who join, and votes of anyone
+            // who left must not be taken over either
+            let error = if session.banned_names.contains(&participant_name) {
+                Some(SessionJoinError::ParticipantNameBanned)
+            } else if session.is_name_in_use(&participant_name) {
+                Some(SessionJoinError::ParticipantNameTaken)
+            } else {
+                None
+            };
+            if let Some(error) = error {
+                self.reject(participant_id, "ParticipantRenameRequest", error.into());
+                // for older clients
+                self.send_message(
+                    participant_id,
+                    PokerMessage::ParticipantRenameErrorResponse {
+                        session_id,
+                        participant_name,
+                        error,
+                    },
+                );
+                return;
+            }
+
+            session.record_change(SessionChange::ParticipantRenamed {
+                old_participant_name: old_participant_name.clone(),
+                participant_name: participant_name.clone(),
+            });
+            let event = session.record_event(PokerMessage::ParticipantRenameAnnouncement {
+                old_participant_name,
+                participant_name,
+            });
+            let participant_ids = session.participant_ids();
+            self.broadcast(&participant_ids, event);
+        } else {
+            self.reject(participant_id, "ParticipantRenameRequest", ErrorCode::NotInSession);
+        }
+    }
+
+    fn handle_participant_kick_request(
+        &mut self,
+        session_id: SessionId,
+        participant_id: u32,
+        participant_name: String,
+        ban: bool,
+        reason: Option<String>,
+    ) {
+        if !self.authorize_facilitator(session_id, participant_id, "ParticipantKickRequest") {
+            return;
+        }
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            let kicked = session
+                .participants
+                .iter()
+                .find(|p| p.name == participant_name)
+                .map(|kicked| (kicked.id, kicked.is_away()));
+            if kicked.is_none() && !ban {
+                self.reject(
+                    participant_id,
+                    "ParticipantKickRequest",
+                    ErrorCode::UnknownParticipant,
+                );
+                return;
+            }
+            session.record_change(SessionChange::ParticipantKicked {
+                participant_name,
+                ban,
+            });
+            let (kicked_id, kicked_is_away) = match kicked {
+                Some(kicked) => kicked,
+                // banning a name nobody uses right now is fine
+                None => return,
+            };
+            if !kicked_is_away {
+                self.send_message(
+                    kicked_id,
+                    PokerMessage::ParticipantKickAnnouncement { session_id, reason },
+                );
+            }
+            self.remove_participant(session_id, kicked_id);
+        }
+    }
+
     fn handle_topic_change_request(
         &mut self,
-        session_id: u32,
-        _participant_id: u32,
+        session_id: SessionId,
+        participant_id: u32,
         trello_card: String,
     ) {
+        if !self.authorize_facilitator(session_id, participant_id, "TopicChangeRequest") {
+            return;
+        }
+        self.change_topic(session_id, trello_card);
+    }
+
+    // start over with a new issue, unless the card didn't change
+    fn change_topic(&mut self, session_id: SessionId, trello_card: String) {
         if let Some(session) = self.sessions.get_mut(&session_id) {
-            let trello_card: Option<String> = if trello_card.len() > 0 {
+            let trello_card: Option<String> = if !trello_card.is_empty() {
                 Some(trello_card)
             } else {
                 None
@@ -495,77 +2303,536 @@ impl Server {
                 return;
             }
             let issue = VotingIssue::new(trello_card);
-            session.current_issue = issue.clone();
+            session.record_change(SessionChange::IssueChanged {
+                issue: issue.clone(),
+            });
+            let event =
+                session.record_event(PokerMessage::VotingIssueAnnouncement { voting_issue: issue });
             let participant_ids = session.participant_ids();
-            participant_ids.iter().for_each(|p| {
+            self.broadcast(&participant_ids, event);
+        }
+    }
+
+    fn handle_issue_history_request(&self, session_id: SessionId, participant_id: u32) {
+        match self.sessions.get(&session_id) {
+            Some(session) if session.participants.iter().any(|p| p.id == participant_id) => {
                 self.send_message(
-                    *p,
-                    PokerMessage::VotingIssueAnnouncement {
-                        voting_issue: issue.clone(),
+                    participant_id,
+                    PokerMessage::IssueHistoryResponse {
+                        session_id,
+                        issues: session.history.clone(),
                     },
                 );
+            }
+            _ => self.reject(participant_id, "IssueHistoryRequest", ErrorCode::NotInSession),
+        }
+    }
+
+    // replay what the participant missed, or send the whole state if that's not possible anymore
+    fn handle_resync_request(
+        &self,
+        session_id: SessionId,
+        participant_id: u32,
+        seq: SequenceNumber,
+    ) {
+        let session = self.sessions.get(&session_id);
+        let participant = session
+            .and_then(|session| session.participants.iter().find(|p| p.id == participant_id));
+        let (session, participant) = match (session, participant) {
+            (Some(session), Some(participant)) => (session, participant),
+            _ => {
+                self.reject(participant_id, "ResyncRequest", ErrorCode::NotInSession);
+                return;
+            }
+        };
+        match session.events_since(seq) {
+            Some(events) => {
+                for event in events {
+                    let message = event.message.blinded_for(&participant.name);
+                    self.send_event(participant_id, event.seq, message);
+                }
+            }
+            None => self.send_message(participant_id, session.session_info(&participant.name)),
+        }
+    }
+
+    fn handle_voting_start_request(
+        &mut self,
+        session_id: SessionId,
+        issue_id: u32,
+        participant_id: u32,
+        countdown_seconds: u64,
+        ctx: &mut Context<Server>,
+    ) {
+        if !self.authorize_facilitator(session_id, participant_id, "VotingStartRequest") {
+            return;
+        }
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            if let Err(code) = session.check_current_issue(issue_id, VotingState::Opening) {
+                self.reject(participant_id, "VotingStartRequest", code);
+                return;
+            }
+            let countdown_seconds = countdown_seconds.min(MAX_VOTING_COUNTDOWN_SECONDS);
+            if countdown_seconds == 0 {
+                self.start_voting(session_id, issue_id);
+                return;
+            }
+            let event = session.record_event(PokerMessage::VotingCountdownAnnouncement {
+                issue_id,
+                countdown_seconds,
+            });
+            let participant_ids = session.participant_ids();
+            self.broadcast(&participant_ids, event);
+            ctx.run_later(Duration::from_secs(countdown_seconds), move |act, _| {
+                act.start_voting(session_id, issue_id);
             });
         }
     }
 
-    fn handle_vote_request(
+    // the issue might have changed or started already while the countdown was running
+    fn start_voting(&mut self, session_id: SessionId, issue_id: u32) {
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            if session.current_issue.id != issue_id
+                || session.current_issue.state != VotingState::Opening
+            {
+                return;
+            }
+            session.record_change(SessionChange::VotingStarted { issue_id });
+            let event = session.record_event(PokerMessage::VotingStateAnnouncement {
+                issue_id,
+                state: VotingState::Voting,
+            });
+            let participant_ids = session.participant_ids();
+            self.persist_session(session_id);
+            self.broadcast(&participant_ids, event);
+        }
+    }
+
+    // only issues whose votes were revealed can be voted on again
+    fn handle_new_round_request(
         &mut self,
-        session_id: u32,
+        session_id: SessionId,
         issue_id: u32,
         participant_id: u32,
-        vote: Vote,
     ) {
+        if !self.authorize_facilitator(session_id, participant_id, "NewRoundRequest") {
+            return;
+        }
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            if let Err(code) = session.check_current_issue(issue_id, VotingState::Closing) {
+                self.reject(participant_id, "NewRoundRequest", code);
+                return;
+            }
+            session.record_change(SessionChange::NewRoundStarted { issue_id });
+            let round = session.current_issue.round;
+            let event =
+                session.record_event(PokerMessage::NewRoundAnnouncement { issue_id, round });
+            let participant_ids = session.participant_ids();
+            self.broadcast(&participant_ids, event);
+        }
+    }
+
+    fn handle_issue_reopen_request(
+        &mut self,
+        session_id: SessionId,
+        issue_id: u32,
+        participant_id: u32,
+        keep_votes: bool,
+    ) {
+        if !self.authorize_facilitator(session_id, participant_id, "IssueReopenRequest") {
+            return;
+        }
         if let Some(session) = self.sessions.get_mut(&session_id) {
-            if session.current_issue.id != issue_id {
-                // TODO: notify sender about issue id mismatch
+            if let Err(code) = session.check_current_issue(issue_id, VotingState::Closing) {
+                self.reject(participant_id, "IssueReopenRequest", code);
+                return;
+            }
+            if keep_votes && session.all_votes_cast() {
+                self.reject(participant_id, "IssueReopenRequest", ErrorCode::NoVoteMissing);
                 return;
             }
+            session.record_change(SessionChange::IssueReopened {
+                issue_id,
+                keep_votes,
+            });
+            let event = session.record_event(PokerMessage::IssueReopenAnnouncement {
+                voting_issue: session.current_issue.clone(),
+            });
+            // everyone only gets to see their own vote again
+            let recipients: Vec<(u32, String)> = session
+                .participants
+                .iter()
+                .filter(|p| !p.is_away())
+                .map(|p| (p.id, p.name.clone()))
+                .collect();
+            for (participant_id, participant_name) in recipients {
+                let message = event.message.blinded_for(&participant_name);
+                self.send_event(participant_id, event.seq, message);
+            }
+        }
+    }
+
+    fn handle_vote_request(
+        &mut self,
+        session_id: SessionId,
+        issue_id: u32,
+        participant_id: u32,
+        vote: Vote,
+    ) {
+        if let Some(session) = self.sessions.get_mut(&session_id) {
             let participant = session.participants.iter().find(|p| p.id == participant_id);
-            if participant.is_none() || session.current_issue.state == VotingState::Closing {
+            let rejection = match participant {
+                None => Some(ErrorCode::NotInSession),
+                Some(participant) if !participant.is_voter() => Some(ErrorCode::NotAVoter),
+                _ => match session.check_current_issue(issue_id, VotingState::Voting) {
+                    Err(code) => Some(code),
+                    Ok(()) if !session.deck.accepts(&vote) => Some(ErrorCode::VoteNotAccepted),
+                    Ok(()) => None,
+                },
+            };
+            if let Some(code) = rejection {
+                self.reject(participant_id, "VoteRequest", code);
                 return;
             }
             let participant_name = participant.unwrap().name.clone();
-            session
-                .current_issue
-                .votes
-                .insert(participant_name.to_string(), vote);
-            {
-                session.participant_ids().iter().for_each(|&p| {
-                    self.send_message(
-                        p,
-                        PokerMessage::VoteReceiptAnnouncement {
-                            participant_name: participant_name.to_string(),
-                            issue_id,
-                        },
-                    );
-                });
-            }
+            let changed = session.current_issue.votes.contains_key(&participant_name);
+            session.record_change(SessionChange::VoteCast {
+                participant_name: participant_name.clone(),
+                issue_id,
+                vote,
+            });
+            let event = session.record_event(PokerMessage::VoteReceiptAnnouncement {
+                participant_name,
+                issue_id,
+                changed,
+            });
+            let participant_ids = session.participant_ids();
+            self.broadcast(&participant_ids, event);
+        } else {
+            self.reject(participant_id, "VoteRequest", ErrorCode::NotInSession);
         }
         self.reveal_if_everyone_voted(session_id);
     }
 
-    fn reveal_if_everyone_voted(&mut self, session_id: u32) {
+    fn handle_vote_retraction_request(
+        &mut self,
+        session_id: SessionId,
+        issue_id: u32,
+        participant_id: u32,
+    ) {
         if let Some(session) = self.sessions.get_mut(&session_id) {
+            let participant_name = match session
+                .participants
+                .iter()
+                .find(|p| p.id == participant_id)
+            {
+                Some(participant) => participant.name.clone(),
+                None => {
+                    self.reject(participant_id, "VoteRetractionRequest", ErrorCode::NotInSession);
+                    return;
+                }
+            };
+            if let Err(code) = session.check_current_issue(issue_id, VotingState::Voting) {
+                self.reject(participant_id, "VoteRetractionRequest", code);
+                return;
+            }
+            if !session.current_issue.votes.contains_key(&participant_name) {
+                self.reject(participant_id, "VoteRetractionRequest", ErrorCode::NoVoteCast);
+                return;
+            }
+            session.record_change(SessionChange::VoteRetracted {
+                participant_name: participant_name.clone(),
+                issue_id,
+            });
+            let event = session.record_event(PokerMessage::VoteRetractionAnnouncement {
+                participant_name,
+                issue_id,
+            });
             let participant_ids = session.participant_ids();
+            self.broadcast(&participant_ids, event);
+        } else {
+            self.reject(participant_id, "VoteRetractionRequest", ErrorCode::NotInSession);
+        }
+    }
 
-            if !session.all_votes_cast() {
+    fn handle_vote_revelation_request(
+        &mut self,
+        session_id: SessionId,
+        issue_id: u32,
+        participant_id: u32,
+    ) {
+        if !self.authorize_facilitator(session_id, participant_id, "VoteRevelationRequest") {
+            return;
+        }
+        if let Some(session) = self.sessions.get(&session_id) {
+            if let Err(code) = session.check_current_issue(issue_id, VotingState::Voting) {
+                self.reject(participant_id, "VoteRevelationRequest", code);
                 return;
             }
-            let outcome = Vote::Unknown;
-            session.current_issue.outcome = Some(outcome.clone()); // TODO: determine outcome from votes cast
-            session.current_issue.state = VotingState::Closing;
-            let issue_id = session.current_issue.id;
-            let votes = session.current_issue.votes.clone();
-            participant_ids.iter().for_each(|&p| {
-                self.send_message(
-                    p,
-                    PokerMessage::VotingResultsRevelation {
-                        issue_id: issue_id.clone(),
-                        votes: votes.clone(),
-                        outcome: outcome.clone(),
-                    },
-                );
+            self.reveal_votes(session_id);
+        }
+    }
+
+    fn reveal_if_everyone_voted(&mut self, session_id: SessionId) {
+        if let Some(session) = self.sessions.get(&session_id) {
+            if session.current_issue.state != VotingState::Voting || !session.all_votes_cast() {
+                return;
+            }
+            self.reveal_votes(session_id);
+        }
+    }
+
+    // close the current issue and let everyone know how they voted
+    fn reveal_votes(&mut self, session_id: SessionId) {
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            let participant_ids = session.participant_ids();
+            let missing_voters = session.missing_voters();
+            let outcome = session
+                .outcome_strategy
+                .strategy()
+                .determine_outcome(&session.deck.cards(), &session.current_issue.votes);
+            session.record_change(SessionChange::VotesRevealed {
+                issue_id: session.current_issue.id,
+                outcome: outcome.clone(),
+            });
+            let event = session.record_event(PokerMessage::VotingResultsRevelation {
+                issue_id: session.current_issue.id,
+                votes: session.current_issue.votes.clone(),
+                outcome,
+                missing_voters,
             });
+            self.broadcast(&participant_ids, event);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::NoStorage;
+
+    fn card(label: &str) -> Vote {
+        Vote::Card(label.to_string())
+    }
+
+    fn session() -> VotingSession {
+        VotingSession::new(
+            SessionId::generate(),
+            "brave-quiet-otter-4172".to_string(),
+            OutcomeStrategyKind::Standard,
+            Deck::Fibonacci,
+            None,
+        )
+    }
+
+    fn join(session: &mut VotingSession, participant_id: u32, participant_name: &str) {
+        session.record_change(SessionChange::ParticipantJoined {
+            participant_id,
+            participant_name: participant_name.to_string(),
+            role: ParticipantRole::Voter,
+            rejoin_token: generate_token(),
+        });
+    }
+
+    #[test]
+    fn renaming_a_voter_never_overwrites_another_vote() {
+        let mut issue = VotingIssue::new(None);
+        issue.votes.insert("alice".to_string(), card("3"));
+        issue.votes.insert("bob".to_string(), card("5"));
+        issue.rename_voter("alice", "bob");
+        assert_eq!(issue.votes.get("alice"), Some(&card("3")));
+        assert_eq!(issue.votes.get("bob"), Some(&card("5")));
+    }
+
+    // alice is still there, bob voted on the first issue and left after it was revealed
+    fn session_with_former_voter() -> VotingSession {
+        let mut session = session();
+        join(&mut session, 1, "alice");
+        join(&mut session, 2, "bob");
+        let issue_id = session.current_issue.id;
+        session.record_change(SessionChange::VotingStarted { issue_id });
+        session.record_change(SessionChange::VoteCast {
+            participant_name: "bob".to_string(),
+            issue_id,
+            vote: card("5"),
+        });
+        session.record_change(SessionChange::VotesRevealed {
+            issue_id,
+            outcome: card("5"),
+        });
+        session.record_change(SessionChange::IssueChanged {
+            issue: VotingIssue::new(None),
+        });
+        session.record_change(SessionChange::ParticipantLeft {
+            participant_name: "bob".to_string(),
+        });
+        session
+    }
+
+    #[test]
+    fn names_of_former_voters_stay_in_use() {
+        let session = session_with_former_voter();
+        assert!(session.is_name_in_use("alice"));
+        assert!(session.is_name_in_use("bob"));
+        assert!(!session.is_name_in_use("carol"));
+    }
+
+    #[test]
+    fn nobody_can_join_under_the_name_of_a_former_voter() {
+        let mut server = Server::new(Box::new(NoStorage), Duration::from_secs(30));
+        let session = session_with_former_voter();
+        let session_id = session.id;
+        server.sessions.insert(session_id, session);
+        let join = |server: &mut Server, participant_id, participant_name: &str| {
+            server.handle_join_session_request(
+                session_id,
+                participant_id,
+                participant_name.to_string(),
+                ParticipantRole::Voter,
+                None,
+                None,
+            );
+            server.sessions[&session_id]
+                .participants
+                .iter()
+                .any(|p| p.id == participant_id)
+        };
+        assert!(!join(&mut server, 3, "bob"));
+        assert!(join(&mut server, 4, "carol"));
+    }
+
+    fn record_events(session: &mut VotingSession, count: usize) {
+        for _ in 0..count {
+            session.record_event(PokerMessage::ParticipantAwayAnnouncement {
+                participant_name: "alice".to_string(),
+            });
+        }
+    }
+
+    fn seqs(events: Option<Vec<SessionEvent>>) -> Option<Vec<SequenceNumber>> {
+        events.map(|events| events.iter().map(|event| event.seq).collect())
+    }
+
+    #[test]
+    fn events_since_returns_the_events_after_the_given_one() {
+        let mut session = session();
+        assert_eq!(seqs(session.events_since(0)), Some(vec![]));
+        assert_eq!(seqs(session.events_since(1)), None);
+        record_events(&mut session, 3);
+        assert_eq!(seqs(session.events_since(0)), Some(vec![1, 2, 3]));
+        assert_eq!(seqs(session.events_since(2)), Some(vec![3]));
+        assert_eq!(seqs(session.events_since(3)), Some(vec![]));
+        assert_eq!(seqs(session.events_since(4)), None);
+    }
+
+    #[test]
+    fn events_since_gives_up_once_events_were_dropped() {
+        let mut session = session();
+        record_events(&mut session, MAX_RECENT_EVENTS);
+        assert_eq!(seqs(session.events_since(0)).unwrap().len(), MAX_RECENT_EVENTS);
+        record_events(&mut session, 50);
+        // events 1 to 50 were dropped to make room for the latest ones
+        assert_eq!(seqs(session.events_since(49)), None);
+        let events = seqs(session.events_since(50)).unwrap();
+        assert_eq!(events.len(), MAX_RECENT_EVENTS);
+        assert_eq!(events.first(), Some(&51));
+        assert_eq!(events.last(), Some(&150));
+        assert_eq!(seqs(session.events_since(150)), Some(vec![]));
+    }
+
+    #[test]
+    fn events_since_only_knows_the_latest_event_after_a_restart() {
+        let mut session = session();
+        session.last_seq = 42;
+        assert_eq!(seqs(session.events_since(42)), Some(vec![]));
+        assert_eq!(seqs(session.events_since(41)), None);
+        assert_eq!(seqs(session.events_since(43)), None);
+        record_events(&mut session, 1);
+        assert_eq!(seqs(session.events_since(42)), Some(vec![43]));
+        assert_eq!(seqs(session.events_since(41)), None);
+    }
+
+    #[test]
+    fn replaying_the_log_rebuilds_the_session() {
+        let mut session = session();
+        join(&mut session, 1, "alice");
+        join(&mut session, 2, "bob");
+        join(&mut session, 3, "carol");
+        let first_issue_id = session.current_issue.id;
+        session.record_change(SessionChange::VotingStarted {
+            issue_id: first_issue_id,
+        });
+        for (participant_name, vote) in &[("alice", "3"), ("bob", "8"), ("carol", "5")] {
+            session.record_change(SessionChange::VoteCast {
+                participant_name: participant_name.to_string(),
+                issue_id: first_issue_id,
+                vote: card(vote),
+            });
+        }
+        session.record_change(SessionChange::VotesRevealed {
+            issue_id: first_issue_id,
+            outcome: card("5"),
+        });
+        session.record_change(SessionChange::NewRoundStarted {
+            issue_id: first_issue_id,
+        });
+        session.record_change(SessionChange::VoteCast {
+            participant_name: "bob".to_string(),
+            issue_id: first_issue_id,
+            vote: card("5"),
+        });
+        session.record_change(SessionChange::ParticipantRenamed {
+            old_participant_name: "bob".to_string(),
+            participant_name: "robert".to_string(),
+        });
+        session.record_change(SessionChange::ParticipantKicked {
+            participant_name: "carol".to_string(),
+            ban: true,
+        });
+        session.record_change(SessionChange::ParticipantLeft {
+            participant_name: "carol".to_string(),
+        });
+        session.record_change(SessionChange::VotesRevealed {
+            issue_id: first_issue_id,
+            outcome: card("5"),
+        });
+        session.record_change(SessionChange::IssueChanged {
+            issue: VotingIssue::new(Some("https://trello.com/c/abc".to_string())),
+        });
+        let second_issue_id = session.current_issue.id;
+        session.record_change(SessionChange::VotingStarted {
+            issue_id: second_issue_id,
+        });
+        session.record_change(SessionChange::VoteCast {
+            participant_name: "alice".to_string(),
+            issue_id: second_issue_id,
+            vote: Vote::Unknown,
+        });
+        session.record_change(SessionChange::FacilitatorChanged {
+            participant_name: "robert".to_string(),
+        });
+        session.record_change(SessionChange::ParticipantAway {
+            participant_name: "alice".to_string(),
+        });
+
+        // the log goes through the storage like it does across a restart
+        let stored_log: Vec<LogEntry> =
+            serde_json::from_str(&serde_json::to_string(&session.log).unwrap()).unwrap();
+        let replayed = VotingSession::replay(&stored_log).unwrap();
+        assert_eq!(
+            serde_json::to_value(&replayed).unwrap(),
+            serde_json::to_value(&session).unwrap()
+        );
+        assert_eq!(replayed.facilitator, "robert");
+        assert_eq!(replayed.banned_names, vec!["carol".to_string()]);
+        assert_eq!(replayed.history.len(), 1);
+    }
+
+    #[test]
+    fn only_logs_starting_with_the_creation_can_be_replayed() {
+        let mut session = session();
+        join(&mut session, 1, "alice");
+        assert!(VotingSession::replay(&session.log[1..]).is_none());
+        assert!(VotingSession::replay(&[]).is_none());
+    }
+}
@@ -0,0 +1,225 @@
+//! Every change to a session is recorded in its log, which the session can be rebuilt from
+//!
+//! Sessions only ever change by applying a `SessionChange`, and each applied change is appended
+//! to the session's log together with the time it happened. Folding the log of a session from its
+//! creation onwards yields the very same state again, which is how sessions are restored after a
+//! restart. The log also serves as an audit trail of a refinement meeting.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::deck::Deck;
+use crate::outcome::OutcomeStrategyKind;
+use crate::passphrase::PassphraseHash;
+use crate::poker_server::{ParticipantRole, SessionId, Vote, VotingIssue};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SessionChange {
+    // always the first change of a session, nobody has joined it yet
+    SessionCreated {
+        session_id: SessionId,
+        invite_code: String,
+        outcome_strategy: OutcomeStrategyKind,
+        deck: Deck,
+        passphrase_hash: Option<PassphraseHash>,
+        issue: VotingIssue,
+    },
+    // the first participant to join becomes the facilitator
+    ParticipantJoined {
+        participant_id: u32,
+        participant_name: String,
+        role: ParticipantRole,
+        rejoin_token: String,
+    },
+    ParticipantAway {
+        participant_name: String,
+    },
+    // someone took their seat back, possibly from another connection
+    ParticipantReturned {
+        participant_id: u32,
+        participant_name: String,
+    },
+    // if the facilitator left, the longest-serving participant takes over
+    ParticipantLeft {
+        participant_name: String,
+    },
+    ParticipantRenamed {
+        old_participant_name: String,
+        participant_name: String,
+    },
+    // drops their unrevealed vote; they leave the session separately
+    ParticipantKicked {
+        participant_name: String,
+        ban: bool,
+    },
+    FacilitatorChanged {
+        participant_name: String,
+    },
+    // the previous issue goes to the history if its votes were revealed
+    IssueChanged {
+        issue: VotingIssue,
+    },
+    VotingStarted {
+        issue_id: u32,
+    },
+    NewRoundStarted {
+        issue_id: u32,
+    },
+    IssueReopened {
+        issue_id: u32,
+        keep_votes: bool,
+    },
+    VoteCast {
+        participant_name: String,
+        issue_id: u32,
+        vote: Vote,
+    },
+    VoteRetracted {
+        participant_name: String,
+        issue_id: u32,
+    },
+    VotesRevealed {
+        issue_id: u32,
+        outcome: Vote,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogEntry {
+    // unix timestamp of when the change was applied
+    pub at: u64,
+    pub change: SessionChange,
+}
+
+impl LogEntry {
+    // the entry without the secrets of the session and its participants
+    fn redacted(&self) -> LogEntry {
+        let change = match &self.change {
+            SessionChange::SessionCreated {
+                session_id,
+                invite_code,
+                outcome_strategy,
+                deck,
+                issue,
+                ..
+            } => SessionChange::SessionCreated {
+                session_id: *session_id,
+                invite_code: invite_code.clone(),
+                outcome_strategy: *outcome_strategy,
+                deck: deck.clone(),
+                passphrase_hash: None,
+                issue: issue.clone(),
+            },
+            SessionChange::ParticipantJoined {
+                participant_id,
+                participant_name,
+                role,
+                ..
+            } => SessionChange::ParticipantJoined {
+                participant_id: *participant_id,
+                participant_name: participant_name.clone(),
+                role: *role,
+                rejoin_token: String::new(),
+            },
+            change => change.clone(),
+        };
+        LogEntry { at: self.at, change }
+    }
+}
+
+// the log as anyone may see it: without secrets, and without the votes of rounds that weren't
+// revealed yet, so the log can't be used to peek at them
+pub fn redacted_log(log: &[LogEntry]) -> Vec<LogEntry> {
+    // going backwards, every vote that was cast before its issue's votes were revealed is public
+    let mut revealed_issue_ids = HashSet::new();
+    let mut redacted: Vec<LogEntry> = log
+        .iter()
+        .rev()
+        .map(|entry| match &entry.change {
+            SessionChange::VotesRevealed { issue_id, .. } => {
+                revealed_issue_ids.insert(*issue_id);
+                entry.redacted()
+            }
+            SessionChange::VoteCast {
+                participant_name,
+                issue_id,
+                ..
+            } if !revealed_issue_ids.contains(issue_id) => LogEntry {
+                at: entry.at,
+                change: SessionChange::VoteCast {
+                    participant_name: participant_name.clone(),
+                    issue_id: *issue_id,
+                    vote: Vote::Secret,
+                },
+            },
+            _ => entry.redacted(),
+        })
+        .collect();
+    redacted.reverse();
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(change: SessionChange) -> LogEntry {
+        LogEntry { at: 0, change }
+    }
+
+    fn vote_cast(participant_name: &str, issue_id: u32, label: &str) -> LogEntry {
+        entry(SessionChange::VoteCast {
+            participant_name: participant_name.to_string(),
+            issue_id,
+            vote: Vote::Card(label.to_string()),
+        })
+    }
+
+    fn votes(log: &[LogEntry]) -> Vec<Vote> {
+        log.iter()
+            .filter_map(|entry| match &entry.change {
+                SessionChange::VoteCast { vote, .. } => Some(vote.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn votes_stay_secret_until_they_are_revealed() {
+        let log = vec![
+            vote_cast("alice", 1, "3"),
+            vote_cast("bob", 1, "13"),
+        ];
+        assert_eq!(votes(&redacted_log(&log)), vec![Vote::Secret, Vote::Secret]);
+    }
+
+    #[test]
+    fn only_the_votes_of_revealed_rounds_are_shown() {
+        let log = vec![
+            vote_cast("alice", 1, "3"),
+            entry(SessionChange::VotesRevealed {
+                issue_id: 1,
+                outcome: Vote::Card("3".to_string()),
+            }),
+            entry(SessionChange::NewRoundStarted { issue_id: 1 }),
+            vote_cast("alice", 1, "5"),
+        ];
+        assert_eq!(
+            votes(&redacted_log(&log)),
+            vec![Vote::Card("3".to_string()), Vote::Secret]
+        );
+    }
+
+    #[test]
+    fn rejoin_tokens_are_left_out() {
+        let log = vec![entry(SessionChange::ParticipantJoined {
+            participant_id: 1,
+            participant_name: "alice".to_string(),
+            role: ParticipantRole::Voter,
+            rejoin_token: "secret".to_string(),
+        })];
+        let redacted = serde_json::to_string(&redacted_log(&log)).unwrap();
+        assert!(!redacted.contains("secret"));
+    }
+}
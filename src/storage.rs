@@ -0,0 +1,185 @@
+//! Storage backends that keep the log of every session so it survives a restart of the server
+//!
+//! The `Server` keeps working on its own sessions and appends the changes of a session to its
+//! stored log as they happen; the logs are only read once when the server starts, and the sessions
+//! are rebuilt from them.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::poker_server::{SequenceNumber, SessionId};
+use crate::session_log::LogEntry;
+
+// the log of a session as it was stored
+pub struct StoredLog {
+    pub entries: Vec<LogEntry>,
+    // sequence number of the session's latest event when the log was last appended to
+    pub last_seq: SequenceNumber,
+}
+
+pub trait SessionStorage {
+    fn load_all(&self) -> io::Result<Vec<StoredLog>>;
+
+    // add the entries to the end of the session's log, which is created by its first entry
+    fn append(
+        &mut self,
+        session_id: SessionId,
+        entries: &[LogEntry],
+        last_seq: SequenceNumber,
+    ) -> io::Result<()>;
+
+    fn remove(&mut self, session_id: SessionId) -> io::Result<()>;
+}
+
+// sessions only live in the server's memory, so there's nothing to restore after a restart
+pub struct NoStorage;
+
+impl SessionStorage for NoStorage {
+    fn load_all(&self) -> io::Result<Vec<StoredLog>> {
+        Ok(Vec::new())
+    }
+
+    fn append(&mut self, _: SessionId, _: &[LogEntry], _: SequenceNumber) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn remove(&mut self, _: SessionId) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// a line of a stored log, the entry along with the session's sequence number when it was written
+#[derive(Serialize)]
+struct StoredEntryRef<'a> {
+    seq: SequenceNumber,
+    #[serde(flatten)]
+    entry: &'a LogEntry,
+}
+
+#[derive(Deserialize)]
+struct StoredEntry {
+    seq: SequenceNumber,
+    #[serde(flatten)]
+    entry: LogEntry,
+}
+
+// stores the log of every session as a file named after its id, with one JSON entry per line
+pub struct FileStorage {
+    directory: PathBuf,
+}
+
+impl FileStorage {
+    pub fn open(directory: PathBuf) -> io::Result<FileStorage> {
+        fs::create_dir_all(&directory)?;
+        Ok(FileStorage { directory })
+    }
+
+    fn session_path(&self, session_id: SessionId) -> PathBuf {
+        self.directory.join(format!("{}.log", session_id))
+    }
+}
+
+impl SessionStorage for FileStorage {
+    fn load_all(&self) -> io::Result<Vec<StoredLog>> {
+        let mut logs = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if path.extension().map_or(true, |extension| extension != "log") {
+                continue;
+            }
+            let mut log = StoredLog {
+                entries: Vec::new(),
+                last_seq: 0,
+            };
+            for line in fs::read_to_string(&path)?.lines() {
+                // a crash while appending can leave a partly written line at the end
+                match serde_json::from_str::<StoredEntry>(line) {
+                    Ok(stored) => {
+                        log.entries.push(stored.entry);
+                        log.last_seq = stored.seq;
+                    }
+                    Err(e) => {
+                        println!("Ignoring the rest of {}: {}", path.display(), e);
+                        break;
+                    }
+                }
+            }
+            logs.push(log);
+        }
+        Ok(logs)
+    }
+
+    fn append(
+        &mut self,
+        session_id: SessionId,
+        entries: &[LogEntry],
+        last_seq: SequenceNumber,
+    ) -> io::Result<()> {
+        let mut lines = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut lines, &StoredEntryRef { seq: last_seq, entry })?;
+            lines.push(b'\n');
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.session_path(session_id))?
+            .write_all(&lines)
+    }
+
+    fn remove(&mut self, session_id: SessionId) -> io::Result<()> {
+        match fs::remove_file(self.session_path(session_id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_log::SessionChange;
+
+    fn entry(participant_name: &str) -> LogEntry {
+        LogEntry {
+            at: 0,
+            change: SessionChange::ParticipantLeft {
+                participant_name: participant_name.to_string(),
+            },
+        }
+    }
+
+    fn names(log: &StoredLog) -> Vec<String> {
+        log.entries
+            .iter()
+            .map(|entry| match &entry.change {
+                SessionChange::ParticipantLeft { participant_name } => participant_name.clone(),
+                change => panic!("unexpected change {:?}", change),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn appended_entries_are_loaded_in_order() {
+        let directory = std::env::temp_dir().join(format!("compoker-test-{}", std::process::id()));
+        let mut storage = FileStorage::open(directory.clone()).unwrap();
+        let session_id = SessionId::generate();
+        storage.append(session_id, &[entry("alice"), entry("bob")], 4).unwrap();
+        storage.append(session_id, &[entry("carol")], 7).unwrap();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(storage.session_path(session_id))
+            .unwrap();
+        file.write_all(b"{\"seq\":9,\"at\":0,\"cha").unwrap();
+
+        let logs = storage.load_all().unwrap();
+        storage.remove(session_id).unwrap();
+        fs::remove_dir(directory).unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(names(&logs[0]), vec!["alice", "bob", "carol"]);
+        assert_eq!(logs[0].last_seq, 7);
+    }
+}
@@ -1,12 +1,30 @@
 import {Writable, writable} from "svelte/store";
 import fireConfetti from "./confetti";
 
-let socket = new WebSocket(process.env.SERVER_URL);
+// the version of the protocol and the optional features of it this client speaks
+const PROTOCOL_VERSION = 2;
+const CAPABILITIES = ["CustomDecks", "ParticipantRoles", "VotingCountdown"];
+
+// the handshake goes out before anything else is sent through the socket
+function connect(): WebSocket {
+    const newSocket = new WebSocket(process.env.SERVER_URL);
+    newSocket.addEventListener("open", (_) => {
+        newSocket.send(JSON.stringify({
+            HandshakeRequest: {
+                protocol_version: PROTOCOL_VERSION,
+                capabilities: CAPABILITIES,
+            }
+        }));
+    });
+    return newSocket;
+}
+
+let socket = connect();
 
 async function rejoinSession() {
     if (currentSession.id) {
         waitForOpenSocket().then(() => {
-            joinSession(currentSession.id, currentSession.my_name)
+            joinSession(currentSession.id, currentSession.my_name, currentSession.my_role)
         })
     }
 }
@@ -19,7 +37,7 @@ async function waitForOpenSocket() {
             })
             socket.addEventListener("close", (_) => {
                 // JS doesn't support reconnecting, need to create a new websocket
-                socket = new WebSocket(process.env.SERVER_URL);
+                socket = connect();
                 rejoinSession();
             })
             setTimeout(() => {
@@ -39,22 +57,29 @@ function sendJson(data) {
     }
 }
 
+// the votes which aren't cards; all other votes are the label of a card from the session's deck
 export enum Vote {
     Secret = "Secret",
     Unknown = "Unknown",
-    One = "One",
-    Two = "Two",
-    Three = "Three",
-    Five = "Five",
-    Eight = "Eight",
-    Thirteen = "Thirteen",
-    TwentyOne = "TwentyOne",
-    Infinite = "Infinite",
+}
+
+export interface Card {
+    label: string,
+    value: number | null,
 }
 
 export enum SessionJoinError {
     UnknownSession = "UnknownSession",
     ParticipantNameTaken = "ParticipantNameTaken",
+    InvalidDeck = "InvalidDeck",
+    WrongPassphrase = "WrongPassphrase",
+    TooManyAttempts = "TooManyAttempts",
+    ParticipantNameBanned = "ParticipantNameBanned",
+}
+
+export enum ParticipantRole {
+    Voter = "Voter",
+    Observer = "Observer",
 }
 
 export enum VotingState {
@@ -65,18 +90,37 @@ export enum VotingState {
 
 export interface VotingSession {
     error: SessionJoinError | null,
-    id: number,
+    // set once the facilitator removed us from the session
+    kick_reason: string | null,
+    // set if we couldn't take the name we asked for
+    rename_error: SessionJoinError | null,
+    id: string | null,
+    invite_code: string,
     my_name: string,
+    my_role: ParticipantRole,
+    rejoin_token: string | null,
     participants: string[],
+    observers: string[],
+    away_participants: string[],
     current_issue: VotingIssue,
+    deck: Card[],
+    facilitator: string,
 }
 
 export interface VotingIssue {
     id: number,
     state: VotingState,
-    votes: Record<string, Vote>,
+    votes: Record<string, string>,
     trello_card: string | null,
-    outcome: Vote
+    outcome: string,
+    round: number,
+    previous_rounds: VotingRound[],
+}
+
+export interface VotingRound {
+    round: number,
+    outcome: string | null,
+    votes: Record<string, string>,
 }
 
 export interface UserInfo {
@@ -87,18 +131,31 @@ export interface UserInfo {
 export type Store<T> = Writable<T> & { get(): T };
 
 interface SessionStore extends Store<Partial<VotingSession>> {
-    createSession(name: string);
+    createSession(name: string, role?: ParticipantRole, passphrase?: string);
+
+    joinSession(session: string, name: string, role?: ParticipantRole, passphrase?: string);
+
+    kickParticipant(participant_name: string, ban?: boolean);
 
-    joinSession(session_id: number, name: string);
+    renameParticipant(participant_name: string);
 }
 
 export type UserInfoStore = Writable<Record<string, UserInfo>>;
 
 const blankSession: Partial<VotingSession> = {
     error: null,
-    id: 0,
+    kick_reason: null,
+    rename_error: null,
+    id: null,
+    invite_code: "",
     my_name: "",
-    participants: []
+    my_role: ParticipantRole.Voter,
+    rejoin_token: null,
+    participants: [],
+    observers: [],
+    away_participants: [],
+    deck: [],
+    facilitator: "",
 }
 
 const blankIssue: VotingIssue = {
@@ -107,28 +164,68 @@ const blankIssue: VotingIssue = {
     votes: {},
     state: VotingState.Opening,
     outcome: Vote.Unknown,
+    round: 1,
+    previous_rounds: [],
 }
 
 const LOCAL_STORAGE_KEY = "session";
 let currentSession: Partial<VotingSession>;
 let currentIssue: VotingIssue;
-let myVote: Vote;
-
-function createSession(my_name: string) {
+let myVote: string;
+// sequence number of the latest session event that was applied
+let lastSeq = 0;
+// whether the events since lastSeq were requested from the server already
+let resyncing = false;
+// the session id or invite code we last asked to join, to try again with another name
+let requestedSession: string = null;
+
+function createSession(my_name: string, my_role: ParticipantRole = ParticipantRole.Voter, passphrase: string = null) {
     currentSession.my_name = my_name;
+    currentSession.my_role = my_role;
     sendJson({
         CreateSessionRequest: {
             participant_name: my_name,
+            role: my_role,
+            passphrase,
         }
     });
 }
 
-function joinSession(session_id: number, my_name: string) {
+// sessions can be joined either by their id or by their invite code
+const SESSION_ID_PATTERN = /^[0-9a-f]{32}$/i;
+
+// the rejoin token is enough to get back into a session with a passphrase
+function joinSession(session: string, my_name: string, my_role: ParticipantRole = ParticipantRole.Voter, passphrase: string = null) {
+    const is_session_id = SESSION_ID_PATTERN.test(session);
+    const rejoin_token = session == currentSession.id && my_name == currentSession.my_name ? currentSession.rejoin_token : null;
+    requestedSession = session;
     currentSession.my_name = my_name;
+    currentSession.my_role = my_role;
     sendJson({
         JoinSessionRequest: {
             participant_name: my_name,
-            session_id
+            session_id: is_session_id ? session : undefined,
+            invite_code: is_session_id ? undefined : session,
+            role: my_role,
+            rejoin_token,
+            passphrase,
+        }
+    })
+}
+
+function renameParticipant(participant_name: string) {
+    sendJson({
+        ParticipantRenameRequest: {
+            participant_name,
+        }
+    })
+}
+
+function kickParticipant(participant_name: string, ban: boolean = false) {
+    sendJson({
+        ParticipantKickRequest: {
+            participant_name,
+            ban,
         }
     })
 }
@@ -141,7 +238,24 @@ function changeTopic(trello_card: string) {
     })
 }
 
-function castVote(vote: Vote) {
+function startVoting(countdown_seconds: number = 0) {
+    sendJson({
+        VotingStartRequest: {
+            issue_id: currentIssue.id,
+            countdown_seconds,
+        },
+    })
+}
+
+function startNewRound() {
+    sendJson({
+        NewRoundRequest: {
+            issue_id: currentIssue.id,
+        },
+    })
+}
+
+function castVote(vote: string) {
     myVote = vote;
     sendJson({
         VoteRequest: {
@@ -151,10 +265,45 @@ function castVote(vote: Vote) {
     })
 }
 
+function revealVotes() {
+    sendJson({
+        VoteRevelationRequest: {
+            issue_id: currentIssue.id,
+        },
+    })
+}
+
+function reopenIssue(keep_votes: boolean) {
+    sendJson({
+        IssueReopenRequest: {
+            issue_id: currentIssue.id,
+            keep_votes,
+        },
+    })
+}
+
+function retractVote() {
+    sendJson({
+        VoteRetractionRequest: {
+            issue_id: currentIssue.id,
+        },
+    })
+}
+
 interface IssueStore extends Writable<VotingIssue> {
     changeTopic(trello_card: string);
 
-    castVote(vote: Vote);
+    startVoting(countdown_seconds?: number);
+
+    startNewRound();
+
+    revealVotes();
+
+    reopenIssue(keep_votes: boolean);
+
+    castVote(vote: string);
+
+    retractVote();
 }
 
 function createIssueStore(): IssueStore {
@@ -164,7 +313,12 @@ function createIssueStore(): IssueStore {
         set,
         update,
         changeTopic,
-        castVote
+        startVoting,
+        startNewRound,
+        revealVotes,
+        reopenIssue,
+        castVote,
+        retractVote,
     }
 }
 
@@ -213,13 +367,9 @@ async function lookupUserInfoFromGitlab(username: string): Promise<UserInfo | un
     }
 }
 
-function consensusReached(votes: Vote[]) {
-    let lastVote: Vote = null;
+function consensusReached(votes: string[]) {
+    let lastVote: string = null;
     for (var vote of votes) {
-        // TODO: once observer mode is available, "unknown" votes can count again
-        if (vote == Vote.Unknown) {
-            continue
-        }
         if (lastVote == null) {
             lastVote = vote
         } else {
@@ -231,7 +381,7 @@ function consensusReached(votes: Vote[]) {
     return true
 }
 
-function fireConfettiIfConsensusReached(votes: Vote[]) {
+function fireConfettiIfConsensusReached(votes: string[]) {
     if (!consensusReached(votes)) {
         return
     }
@@ -276,7 +426,7 @@ function createUserInfoStore(): UserInfoStore {
 
 function createSessionStore(): SessionStore {
     const saveState = (session: Partial<VotingSession>): Partial<VotingSession> => {
-        localStorage.setItem(LOCAL_STORAGE_KEY, JSON.stringify(session, ["id", "my_name"]));
+        localStorage.setItem(LOCAL_STORAGE_KEY, JSON.stringify(session, ["id", "my_name", "my_role", "rejoin_token"]));
         return currentSession = session;
     }
 
@@ -289,9 +439,9 @@ function createSessionStore(): SessionStore {
             ...blankSession,
             ...JSON.parse(persistedState)
         };
-        if (currentSession.id > 0) {
+        if (currentSession.id) {
             waitForOpenSocket().then(() => {
-                joinSession(currentSession.id, currentSession.my_name);
+                joinSession(currentSession.id, currentSession.my_name, currentSession.my_role);
             })
         }
     }
@@ -309,15 +459,26 @@ function createSessionStore(): SessionStore {
         },
         createSession,
         joinSession,
+        kickParticipant,
+        renameParticipant,
     }
 }
 
 const messageHandlers = {
     SessionInfoResponse: ({
                               session_id,
+                              invite_code,
                               current_issue,
-                              current_participants
-                          }: { session_id: number, current_issue: VotingIssue, current_participants: string[] }) => {
+                              current_participants,
+                              observers,
+                              deck,
+                              facilitator,
+                              away_participants,
+                              rejoin_token,
+                              seq
+                          }: { session_id: string, invite_code: string, current_issue: VotingIssue, current_participants: string[], observers: string[], deck: Card[], facilitator: string, away_participants: string[], rejoin_token: string, seq: number }) => {
+        lastSeq = seq;
+        resyncing = false;
         sessionStore.update((current) => {
                 for (let username of current_participants) {
                     ensureUserInStore(username);
@@ -325,41 +486,161 @@ const messageHandlers = {
                 return {
                     ...current,
                     error: null,
+                    kick_reason: null,
                     id: session_id,
+                    invite_code,
                     participants: current_participants,
+                    observers,
+                    deck,
+                    facilitator,
+                    away_participants,
+                    rejoin_token,
                 }
             }
         );
         issueStore.set(current_issue);
         currentIssue = current_issue;
     },
-    SessionJoinErrorResponse: ({session_id, error}) => {
-        console.log(`Failed to join session: ${error}`);
+    ParticipantKickAnnouncement: ({reason}) => {
+        // forget the session, so we don't rejoin it once the server closes the connection
+        sessionStore.update((current) => {
+            return {
+                ...current,
+                id: null,
+                rejoin_token: null,
+                kick_reason: reason ?? "You were removed from the session",
+            }
+        })
+    },
+    SessionClosedAnnouncement: (_) => {
         sessionStore.update((current) => {
             return {
                 ...current,
-                id: error == SessionJoinError.ParticipantNameTaken ? session_id : null,
-                error: error,
+                id: null,
+                rejoin_token: null,
+                kick_reason: "The session was closed",
             }
         })
     },
-    ParticipantJoinAnnouncement: ({participant_name}) => {
+    ParticipantJoinAnnouncement: ({participant_name, role}) => {
         sessionStore.update((current) => {
             ensureUserInStore(participant_name)
             current.participants.push(participant_name)
+            if (role == ParticipantRole.Observer) {
+                current.observers.push(participant_name)
+            }
             return current
         })
     },
     ParticipantLeaveAnnouncement: ({participant_name}) => {
         sessionStore.update((current) => {
             current.participants = current.participants.filter((p) => p != participant_name)
+            current.observers = current.observers.filter((p) => p != participant_name)
+            current.away_participants = current.away_participants.filter((p) => p != participant_name)
+            return current
+        })
+    },
+    ParticipantRenameAnnouncement: ({old_participant_name, participant_name}) => {
+        const rename = (p) => p == old_participant_name ? participant_name : p;
+        ensureUserInStore(participant_name)
+        sessionStore.update((current) => {
+            current.participants = current.participants.map(rename)
+            current.observers = current.observers.map(rename)
+            current.away_participants = current.away_participants.map(rename)
+            current.facilitator = rename(current.facilitator)
+            if (current.my_name == old_participant_name) {
+                current.my_name = participant_name
+                current.rename_error = null
+            }
+            return current
+        })
+        issueStore.update((current) => {
+            if (current.votes.hasOwnProperty(old_participant_name)) {
+                current.votes[participant_name] = current.votes[old_participant_name]
+                delete current.votes[old_participant_name]
+            }
+            return currentIssue = current
+        })
+    },
+    ParticipantAwayAnnouncement: ({participant_name}) => {
+        sessionStore.update((current) => {
+            current.away_participants.push(participant_name)
+            return current
+        })
+    },
+    ParticipantReturnAnnouncement: ({participant_name}) => {
+        sessionStore.update((current) => {
+            current.away_participants = current.away_participants.filter((p) => p != participant_name)
             return current
         })
     },
+    FacilitatorAnnouncement: ({participant_name}) => {
+        sessionStore.update((current) => {
+            current.facilitator = participant_name
+            return current
+        })
+    },
+    HandshakeResponse: ({protocol_version, capabilities}) => {
+        console.log(`Speaking protocol version ${protocol_version} with ${capabilities.join(", ")}`)
+    },
+    ErrorResponse: ({code, request}) => {
+        console.error(`The server rejected ${request ?? "a message"}: ${code}`)
+        if (request == "CreateSessionRequest" || request == "JoinSessionRequest") {
+            sessionStore.update((current) => {
+                return {
+                    ...current,
+                    id: code == SessionJoinError.ParticipantNameTaken ? requestedSession : null,
+                    error: code,
+                }
+            })
+        } else if (request == "ParticipantRenameRequest") {
+            sessionStore.update((current) => {
+                return {
+                    ...current,
+                    rename_error: code,
+                }
+            })
+        }
+    },
+    AckResponse: ({request_id}) => {
+        console.log(`The server accepted request ${request_id}`)
+    },
     VotingIssueAnnouncement: ({voting_issue}) => {
         issueStore.set(currentIssue = voting_issue)
         myVote = null
     },
+    IssueReopenAnnouncement: ({voting_issue}) => {
+        issueStore.set(currentIssue = voting_issue)
+        myVote = voting_issue.votes[currentSession.my_name] ?? null
+    },
+    VotingCountdownAnnouncement: ({issue_id, countdown_seconds}) => {
+        console.log(`Voting on issue ${issue_id} starts in ${countdown_seconds} seconds`)
+    },
+    VotingStateAnnouncement: ({issue_id, state}) => {
+        issueStore.update((current) => {
+            if (current.id != issue_id) {
+                console.log("Received state of unknown issue")
+                return current
+            }
+            current.state = state
+            return currentIssue = current
+        })
+    },
+    NewRoundAnnouncement: ({issue_id, round}) => {
+        issueStore.update((current) => {
+            if (current.id != issue_id) {
+                console.log("Received new round of unknown issue")
+                return current
+            }
+            current.previous_rounds.push({round: current.round, outcome: current.outcome, votes: current.votes})
+            current.round = round
+            current.votes = {}
+            current.outcome = null
+            current.state = VotingState.Voting
+            return currentIssue = current
+        })
+        myVote = null
+    },
     VoteReceiptAnnouncement: ({participant_name, issue_id}) => {
         issueStore.update((current) => {
             if (current.id != issue_id) {
@@ -374,6 +655,18 @@ const messageHandlers = {
             return currentIssue = current
         })
     },
+    VoteRetractionAnnouncement: ({participant_name, issue_id}) => {
+        issueStore.update((current) => {
+            if (current.id != issue_id) {
+                return current
+            }
+            if (participant_name == currentSession.my_name) {
+                myVote = null
+            }
+            delete current.votes[participant_name]
+            return currentIssue = current
+        })
+    },
     VotingResultsRevelation: ({issue_id, votes, outcome}) => {
         issueStore.update((current) => {
             if (current.id != issue_id) {
@@ -389,11 +682,30 @@ const messageHandlers = {
     }
 }
 
+// events have to be applied in order, so after a gap the missed ones are requested first
+function isNextEvent(seq: number): boolean {
+    if (seq <= lastSeq) {
+        return false;
+    }
+    if (seq > lastSeq + 1) {
+        if (!resyncing) {
+            resyncing = true;
+            sendJson({ResyncRequest: {seq: lastSeq}});
+        }
+        return false;
+    }
+    lastSeq = seq;
+    resyncing = false;
+    return true;
+}
+
 socket.addEventListener("message", function (event) {
     console.log(`Message: ${event.data}`);
     const decoded = JSON.parse(event.data);
-    const messageType = Object.keys(decoded)[0];
-    if (messageHandlers.hasOwnProperty(messageType)) {
+    const messageType = Object.keys(decoded).find((key) => key != "seq");
+    if (decoded.seq !== undefined && !isNextEvent(decoded.seq)) {
+        console.log(`Skipping event ${decoded.seq}, the latest one applied is ${lastSeq}`);
+    } else if (messageHandlers.hasOwnProperty(messageType)) {
         messageHandlers[messageType](decoded[messageType]);
     } else {
         console.log(`Unknown Message: ${event.data}`);